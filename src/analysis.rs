@@ -0,0 +1,156 @@
+use std::{sync::{atomic::{AtomicBool, Ordering}, Arc}, time::{Duration, Instant}};
+
+use bevy::{prelude::*, tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task}};
+use bevy_egui::{egui::{self, Align2}, EguiContexts};
+
+use crate::{bot::alpha_beta::{analyze, score_share, score_text, Candidate}, network::{client::{CurrentGame, ShownGame}, StartClient}, rules::{Game, Player}, GameState};
+
+pub struct AnalysisPlugin;
+
+impl Plugin for AnalysisPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Analysis>()
+        .add_systems(Update, (start_analysis, poll_analysis, analysis_ui_system).chain()
+            .run_if(in_state(GameState::InGame).or_else(in_state(GameState::Replaying))))
+        .add_systems(OnEnter(GameState::FinishingGame), stop_analysis);
+    }
+}
+
+/// Most moves analysis searches ahead
+const ANALYSIS_DEPTH: u32 = 6;
+/// Time analysis of one position gets, big boards are searched less deep
+const ANALYSIS_TIME: Duration = Duration::from_secs(2);
+/// Candidate moves shown in analysis panel
+const CANDIDATES: usize = 3;
+
+/// Engine analysis of the shown position
+#[derive(Resource, Default)]
+struct Analysis {
+    enabled: bool,
+    /// False while analysis would help a player against their opponent
+    allowed: bool,
+    task: Option<Task<(Vec<Candidate>, u32)>>,
+    stop: Arc<AtomicBool>,
+    /// Position the candidates are for
+    game: Option<Game>,
+    candidates: Vec<Candidate>,
+    /// Moves candidates were searched ahead
+    depth: u32,
+}
+
+impl Analysis {
+    fn cancel(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.task = None;
+    }
+    /// Stops analysis and forgets its results
+    fn clear(&mut self) {
+        self.cancel();
+        self.game = None;
+        self.candidates.clear();
+    }
+}
+
+/// True if analysis can't give away moves, hints are the only help in games against someone
+///
+/// Hot-seat games and replays can be analysed at any time, other games once they are over or in positions
+/// before the current one
+fn is_allowed(start_client: &StartClient, game: &Game, shown_game: &Game) -> bool {
+    matches!(start_client, StartClient::Local | StartClient::Replay)
+        || game.outcome().is_some()
+        || shown_game.history().len() < game.history().len()
+}
+
+/// Analyses shown position again whenever it changes, so moves, previews and replays all update it
+fn start_analysis(
+    game: Res<CurrentGame>,
+    shown_game: Res<ShownGame>,
+    start_client: Res<State<StartClient>>,
+    mut analysis: ResMut<Analysis>,
+) {
+    analysis.allowed = is_allowed(start_client.get(), &game.0, &shown_game.0);
+    if !analysis.allowed {
+        if analysis.game.is_some() {
+            analysis.clear();
+        }
+        return;
+    }
+    if !analysis.enabled || (analysis.game.is_some() && !shown_game.is_changed()) {
+        return;
+    }
+    analysis.cancel();
+    analysis.candidates.clear();
+    let game = shown_game.0.clone();
+    analysis.game = Some(game.clone());
+    let stop = Arc::new(AtomicBool::new(false));
+    analysis.stop = stop.clone();
+    analysis.task = Some(AsyncComputeTaskPool::get().spawn(async move {
+        analyze(&game, ANALYSIS_DEPTH, CANDIDATES, &stop, Instant::now() + ANALYSIS_TIME)
+    }));
+}
+
+fn poll_analysis(mut analysis: ResMut<Analysis>) {
+    let Some(task) = &mut analysis.task else {
+        return;
+    };
+    if let Some((candidates, depth)) = block_on(future::poll_once(task)) {
+        analysis.candidates = candidates;
+        analysis.depth = depth;
+        analysis.task = None;
+    }
+}
+
+fn stop_analysis(mut analysis: ResMut<Analysis>) {
+    analysis.clear();
+}
+
+/// Evaluation bar for X, best moves with scores and the line expected after the best one
+fn analysis_ui_system(mut contexts: EguiContexts, mut analysis: ResMut<Analysis>) {
+    egui::Window::new("Analysis").anchor(Align2::RIGHT_TOP, [0.,0.]).show(contexts.ctx_mut(), |ui| {
+        if ui.checkbox(&mut analysis.enabled, "Analyze position").changed() && !analysis.enabled {
+            analysis.clear();
+        }
+        if !analysis.enabled {
+            return;
+        }
+        if !analysis.allowed {
+            ui.label("Available for earlier moves and once the game is over");
+            return;
+        }
+        let Some(game) = &analysis.game else {
+            return;
+        };
+        if let Some(outcome) = game.outcome() {
+            ui.label(match outcome.winner() {
+                Some(player) => format!("Game is over, {} won", player),
+                None => "Game is over, draw".to_string(),
+            });
+            return;
+        }
+        if analysis.task.is_some() {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.label("Analyzing…");
+            });
+            return;
+        }
+        let Some(best) = analysis.candidates.first() else {
+            ui.label("Too many moves to analyze in time");
+            return;
+        };
+        let to_move = game.to_move();
+        // scores are for the player to move, bar always shows X
+        let x_score = match to_move {
+            Player::X => best.score,
+            Player::O => -best.score,
+        };
+        ui.add(egui::ProgressBar::new(score_share(x_score)).text(format!("X {}", score_text(x_score))));
+        let config = game.config();
+        ui.label(format!("Best moves for {}, {} moves ahead", to_move, analysis.depth));
+        for candidate in &analysis.candidates {
+            ui.label(format!("{} {}", candidate.mv.notation(&config), score_text(candidate.score)));
+        }
+        let line: Vec<String> = best.line.iter().map(|mv| mv.notation(&config)).collect();
+        ui.label(format!("Line: {}", line.join(" ")));
+    });
+}
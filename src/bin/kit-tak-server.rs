@@ -0,0 +1,54 @@
+//! Headless server that only hosts games, for boxes without a display:
+//!
+//! ```text
+//! kit-tak-server --address 0.0.0.0 --port 6000 --variant "board=3 outer=3 win=3 depth=2"
+//! ```
+
+use std::{net::{IpAddr, SocketAddr}, time::Duration};
+
+use bevy::{app::ScheduleRunnerPlugin, log::LogPlugin, prelude::*};
+use kit_tak::{network::{server::{DedicatedServerPlugin, ServerAddress}, DEFAULT_PORT}, notation::parse_variant, rules::{GridConfig, Rules}};
+
+/// Server has nothing to draw, so it doesn't need to update more often than this
+const UPDATES_PER_SECOND: f64 = 60.;
+
+/// Reads `--address`, `--port` and `--variant` flags
+fn parse_args(args: &[String]) -> Result<(SocketAddr, GridConfig, Rules), String> {
+    let mut address = ServerAddress::default().0;
+    let (mut config, mut rules) = (GridConfig::default(), Rules::default());
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or_else(|| format!("{} needs a value", flag))?;
+        match flag.as_str() {
+            "--address" => address.set_ip(value.parse::<IpAddr>().map_err(|_| format!("bad address \"{}\"", value))?),
+            "--port" => address.set_port(value.parse().map_err(|_| format!("bad port \"{}\", default is {}", value, DEFAULT_PORT))?),
+            "--variant" => (config, rules) = parse_variant(value).map_err(|error| error.to_string())?,
+            _ => return Err(format!("unknown option {}", flag)),
+        }
+    }
+    if !config.is_valid() {
+        return Err("variant has no valid sizes".to_string());
+    }
+    Ok((address, config, rules))
+}
+
+fn main() {
+    let args = std::env::args().collect::<Vec<String>>();
+    let (address, config, rules) = match parse_args(&args[1..]) {
+        Ok(settings) => settings,
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        },
+    };
+    App::new()
+        .add_plugins((
+            MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(1. / UPDATES_PER_SECOND))),
+            LogPlugin::default(),
+            DedicatedServerPlugin,
+        ))
+        .insert_resource(ServerAddress(address))
+        .insert_resource(config)
+        .insert_resource(rules)
+        .run();
+}
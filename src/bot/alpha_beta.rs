@@ -0,0 +1,342 @@
+//! Depth limited alpha-beta search with a heuristic evaluation of unfinished positions
+
+use std::{sync::atomic::{AtomicBool, Ordering}, time::{Duration, Instant}};
+
+use crate::rules::{BoardState, Board, Game, Move, Player, DIRECTIONS};
+
+use super::Rng;
+
+/// Score of a won game, bigger than any heuristic score
+const WIN: i32 = 1_000_000;
+/// Weight of a board cell that is won, on any level
+const WON_CELL: i32 = 3;
+/// Weight of a line missing only one mark
+const TWO_IN_A_ROW: i32 = 4;
+
+/// Presets of search depth and randomness added to move scores
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+
+impl Difficulty {
+    /// Every preset, in the order menu shows them
+    pub const ALL: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Medium => "Medium",
+            Difficulty::Hard => "Hard",
+        }
+    }
+    /// Moves searched ahead
+    fn depth(&self) -> u32 {
+        match self {
+            Difficulty::Easy => 1,
+            Difficulty::Medium => 3,
+            Difficulty::Hard => 5,
+        }
+    }
+    /// Largest random score added to every move, Hard always plays the same moves
+    fn noise(&self) -> i32 {
+        match self {
+            Difficulty::Easy => 60,
+            Difficulty::Medium => 15,
+            Difficulty::Hard => 0,
+        }
+    }
+    /// Longest the bot thinks about a move, big boards don't let it reach its full depth
+    pub fn time_limit(&self) -> Duration {
+        match self {
+            Difficulty::Easy => Duration::from_secs(1),
+            Difficulty::Medium => Duration::from_secs(2),
+            Difficulty::Hard => Duration::from_secs(4),
+        }
+    }
+}
+
+/// Ends a search when stop flag is set or deadline passes
+struct Limit<'a> {
+    stop: &'a AtomicBool,
+    deadline: Option<Instant>,
+}
+
+impl Limit<'_> {
+    fn is_reached(&self) -> bool {
+        self.stop.load(Ordering::Relaxed) || self.deadline.is_some_and(|deadline| Instant::now() >= deadline)
+    }
+}
+
+/// Counts won cells and lines missing one mark of the player, lines with marks of opponent don't count
+fn board_counts(board: &Board, win_length: usize, player: Player) -> (i32, i32) {
+    let size = board.size() as isize;
+    let won = (0..board.size() * board.size()).filter(|cell| board.get(*cell) == BoardState::Won(player)).count() as i32;
+    let mut twos = 0;
+    for start in 0..board.size() * board.size() {
+        let (x, y) = ((start % board.size()) as isize, (start / board.size()) as isize);
+        for (dx, dy) in DIRECTIONS {
+            let last = win_length as isize - 1;
+            let (end_x, end_y) = (x + dx * last, y + dy * last);
+            if !(0..size).contains(&end_x) || !(0..size).contains(&end_y) {
+                continue;
+            }
+            let mut own = 0;
+            let mut open = 0;
+            for step in 0..win_length as isize {
+                match board.get(((y + dy * step) * size + x + dx * step) as usize) {
+                    BoardState::Won(owner) if owner == player => own += 1,
+                    BoardState::Open => open += 1,
+                    _ => (),
+                }
+            }
+            if own + 1 == win_length && open == 1 {
+                twos += 1;
+            }
+        }
+    }
+    (won, twos)
+}
+
+/// Scores open board with given path and open boards inside of it for the player
+///
+/// Boards on higher levels weigh ten times more than boards they hold
+fn board_score(game: &Game, path: &mut Vec<usize>, player: Player) -> i32 {
+    let config = game.config();
+    let weight = 10_i32.pow((config.depth - 1 - path.len()) as u32);
+    let board = game.board(path);
+    let (own_won, own_twos) = board_counts(board, config.win_length, player);
+    let (other_won, other_twos) = board_counts(board, config.win_length, player.other());
+    let mut score = weight * (WON_CELL * (own_won - other_won) + TWO_IN_A_ROW * (own_twos - other_twos));
+    // lines on main board lose in misère
+    if path.is_empty() && game.rules().misere {
+        score = -score;
+    }
+    if path.len() + 1 < config.depth {
+        for cell in 0..config.level_cells(path.len()) {
+            path.push(cell);
+            if game.board_state(path) == BoardState::Open {
+                score += board_score(game, path, player);
+            }
+            path.pop();
+        }
+    }
+    score
+}
+
+/// Heuristic score of the position for the player, `depth_left` makes faster wins score more
+pub fn evaluate(game: &Game, player: Player, depth_left: u32) -> i32 {
+    if let Some(outcome) = game.outcome() {
+        return match outcome.winner() {
+            Some(winner) if winner == player => WIN + depth_left as i32,
+            Some(_) => -WIN - depth_left as i32,
+            None => 0,
+        };
+    }
+    let config = game.config();
+    // sending the opponent to a finished board gives them a free move
+    let send_penalty = 2 * 10_i32.pow(config.depth as u32 - 1);
+    let free_move = match game.forced_board().is_empty() && config.depth > 1 {
+        true if game.to_move() == player => send_penalty,
+        true => -send_penalty,
+        false => 0,
+    };
+    board_score(game, &mut Vec::new(), player) + free_move
+}
+
+/// Score of the position for the player to move searched `depth` moves ahead, and moves both players
+/// are expected to make
+///
+/// Stopped search returns evaluation of current position, its move isn't used anyway
+fn negamax(game: &Game, depth: u32, mut alpha: i32, beta: i32, limit: &Limit) -> (i32, Vec<Move>) {
+    let player = game.to_move();
+    if depth == 0 || game.outcome().is_some() || limit.is_reached() {
+        return (evaluate(game, player, depth), Vec::new());
+    }
+    let mut best = (-WIN * 2, Vec::new());
+    for mv in game.legal_moves() {
+        let mut next = game.clone();
+        next.apply(mv).expect("legal moves are legal");
+        let (score, line) = negamax(&next, depth - 1, -beta, -alpha, limit);
+        if -score > best.0 {
+            best = (-score, [vec![mv], line].concat());
+        }
+        alpha = alpha.max(-score);
+        if alpha >= beta {
+            break;
+        }
+    }
+    best
+}
+
+/// Move with its score for the player making it and the line expected after it
+#[derive(Clone, Debug)]
+pub struct Candidate {
+    pub mv: Move,
+    pub score: i32,
+    /// Expected moves, starting with `mv`
+    pub line: Vec<Move>,
+}
+
+/// Best `count` moves, best first, and the depth they were searched to
+///
+/// Depths up to `max_depth` are searched one after another like in `best_move`, candidates of the deepest
+/// one finished before stop or `deadline` are returned. Depth is 0 if not even one was finished
+pub fn analyze(game: &Game, max_depth: u32, count: usize, stop: &AtomicBool, deadline: Instant) -> (Vec<Candidate>, u32) {
+    let limit = Limit { stop, deadline: Some(deadline) };
+    let game = &game.without_history();
+    let mut best = (Vec::new(), 0);
+    for depth in 1..=max_depth {
+        let mut candidates = Vec::new();
+        for mv in game.legal_moves() {
+            if limit.is_reached() {
+                break;
+            }
+            let mut next = game.clone();
+            next.apply(mv).expect("legal moves are legal");
+            let (score, line) = negamax(&next, depth - 1, -WIN * 2, WIN * 2, &limit);
+            candidates.push(Candidate {
+                mv,
+                score: -score,
+                line: [vec![mv], line].concat(),
+            });
+        }
+        if limit.is_reached() {
+            break;
+        }
+        candidates.sort_by_key(|candidate| -candidate.score);
+        candidates.truncate(count);
+        best = (candidates, depth);
+    }
+    best
+}
+
+/// Score written for people, won and lost games are told apart from heuristic scores
+pub fn score_text(score: i32) -> String {
+    match score {
+        score if score > WIN / 2 => "win".to_string(),
+        score if score < -WIN / 2 => "loss".to_string(),
+        score => format!("{:+}", score),
+    }
+}
+
+/// Share of the evaluation bar that belongs to the player the score is for, 0.5 is an equal game
+pub fn score_share(score: i32) -> f32 {
+    0.5 + 0.5 * (score as f32 / 200.).tanh()
+}
+
+/// Best move found at the depth of difficulty, None if game is over
+///
+/// Depths are searched one after another until stopped or past `deadline`, then move of the deepest finished
+/// one is returned. Moves with equal score are picked in the order of `Game::legal_moves`, so Hard is
+/// deterministic whenever it reaches its full depth in time
+pub fn best_move(game: &Game, difficulty: Difficulty, rng: &mut Rng, stop: &AtomicBool, deadline: Instant) -> Option<Move> {
+    let limit = Limit { stop, deadline: Some(deadline) };
+    let game = &game.without_history();
+    let mut best = game.legal_moves().first().copied();
+    for depth in 1..=difficulty.depth() {
+        let mv = root_move(game, depth, difficulty.noise(), rng, &limit);
+        if limit.is_reached() {
+            break;
+        }
+        best = mv;
+    }
+    best
+}
+
+/// Best move searched `depth` moves ahead, every score gets random number up to `noise` added
+fn root_move(game: &Game, depth: u32, noise: i32, rng: &mut Rng, limit: &Limit) -> Option<Move> {
+    let mut alpha = -WIN * 2;
+    let mut best = None;
+    for mv in game.legal_moves() {
+        if limit.is_reached() {
+            break;
+        }
+        let mut next = game.clone();
+        next.apply(mv).expect("legal moves are legal");
+        // noisy scores need exact values of every move, so only noiseless search narrows the window
+        let window = if noise == 0 { alpha } else { -WIN * 2 };
+        let mut score = -negamax(&next, depth - 1, -WIN * 2, -window, limit).0;
+        if noise > 0 {
+            score += rng.below(noise as usize + 1) as i32;
+        }
+        if best.is_none() || score > alpha {
+            alpha = score;
+            best = Some(mv);
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::{play_legal, GridConfig, Rules, MAX_CELLS};
+
+    /// Deadline tests of search results don't reach
+    fn unlimited() -> Instant {
+        Instant::now() + Duration::from_secs(3600)
+    }
+
+    #[test]
+    fn hard_always_picks_the_same_move() {
+        let stop = AtomicBool::new(false);
+        for moves in [5, 12] {
+            let mut game = Game::default();
+            play_legal(&mut game, 0, moves);
+            let first = best_move(&game, Difficulty::Hard, &mut Rng::new(1), &stop, unlimited());
+            assert!(first.is_some());
+            for seed in [2, 12345] {
+                assert_eq!(best_move(&game, Difficulty::Hard, &mut Rng::new(seed), &stop, unlimited()), first);
+            }
+        }
+    }
+
+    #[test]
+    fn stopped_search_still_returns_legal_move() {
+        let mut game = Game::default();
+        play_legal(&mut game, 0, 3);
+        let mv = best_move(&game, Difficulty::Hard, &mut Rng::new(1), &AtomicBool::new(true), unlimited()).unwrap();
+        assert!(game.is_legal(mv));
+    }
+
+    #[test]
+    fn every_difficulty_takes_immediate_win() {
+        let stop = AtomicBool::new(false);
+        // O won boards a and b and needs one more mark in board c for a line on main board
+        let boards = ["ooo......", "ooo......", "oo.xx....", "xx.......", ".x.......", "x........", "........x", ".........", "........."];
+        let position = format!("b3o3w3d2 {} oo....... o c", boards.join("/"));
+        let game = Game::from_position(&position, Rules::default()).unwrap();
+        for difficulty in Difficulty::ALL {
+            let mv = best_move(&game, difficulty, &mut Rng::new(9), &stop, Instant::now() + difficulty.time_limit());
+            assert_eq!(mv, Some(Move::new(&[2, 2])), "{}", difficulty.name());
+        }
+    }
+
+    #[test]
+    fn hard_answers_in_time_on_biggest_board() {
+        let config = GridConfig { depth: 4, ..GridConfig::default() };
+        assert_eq!(config.total_cells(), MAX_CELLS);
+        let game = Game::new(config, Rules::default());
+        let limit = Difficulty::Hard.time_limit();
+        let started = Instant::now();
+        let mv = best_move(&game, Difficulty::Hard, &mut Rng::new(1), &AtomicBool::new(false), started + limit);
+        let elapsed = started.elapsed();
+        assert!(game.is_legal(mv.unwrap()));
+        // checking the deadline takes a node or root move at most
+        assert!(elapsed < limit + Duration::from_millis(500), "{:?}", elapsed);
+    }
+
+    #[test]
+    fn analysis_returns_deepest_finished_depth() {
+        let game = Game::default();
+        let (candidates, depth) = analyze(&game, 2, 3, &AtomicBool::new(false), unlimited());
+        assert_eq!((candidates.len(), depth), (3, 2));
+        assert!(candidates.windows(2).all(|pair| pair[0].score >= pair[1].score));
+        let (candidates, depth) = analyze(&game, 2, 3, &AtomicBool::new(false), Instant::now());
+        assert!(candidates.is_empty());
+        assert_eq!(depth, 0);
+    }
+}
@@ -0,0 +1,108 @@
+//! Monte-Carlo tree search, every iteration plays a random game from a leaf of the tree
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::rules::{Game, Move, Player};
+
+use super::{Budget, Rng};
+
+/// Exploration constant of UCT formula
+const EXPLORATION: f32 = 1.41;
+
+struct Node {
+    /// Move leading to this node, None for the root
+    mv: Option<Move>,
+    /// Player who made `mv`, results are counted for them
+    player: Player,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    /// Legal moves that don't have a node yet
+    untried: Vec<Move>,
+    visits: u32,
+    /// Sum of results of playouts through this node, 1 for a win and 0.5 for a draw
+    score: f32,
+}
+
+impl Node {
+    fn new(game: &Game, mv: Option<Move>, parent: Option<usize>) -> Node {
+        Node {
+            mv,
+            player: game.to_move().other(),
+            parent,
+            children: Vec::new(),
+            untried: game.legal_moves(),
+            visits: 0,
+            score: 0.,
+        }
+    }
+    fn uct(&self, parent_visits: u32) -> f32 {
+        self.score / self.visits as f32 + EXPLORATION * ((parent_visits as f32).ln() / self.visits as f32).sqrt()
+    }
+}
+
+/// Result of finished game for given player
+fn score(game: &Game, player: Player) -> f32 {
+    match game.outcome().and_then(|outcome| outcome.winner()) {
+        Some(winner) if winner == player => 1.,
+        Some(_) => 0.,
+        None => 0.5,
+    }
+}
+
+/// Most visited move after searching within the budget or until stopped, None if game is over
+pub fn best_move(game: &Game, budget: Budget, rng: &mut Rng, stop: &AtomicBool) -> Option<Move> {
+    let moves = game.legal_moves();
+    if moves.len() <= 1 {
+        return moves.first().copied();
+    }
+    let root = game.without_history();
+    let mut nodes = vec![Node::new(&root, None, None)];
+    let mut iterations = 0;
+    let started = std::time::Instant::now();
+    while !budget.is_spent(iterations, started) && !stop.load(Ordering::Relaxed) {
+        iterations += 1;
+        let mut position = root.clone();
+        // selection, going down fully expanded nodes
+        let mut node = 0;
+        while nodes[node].untried.is_empty() && !nodes[node].children.is_empty() {
+            let parent_visits = nodes[node].visits;
+            node = *nodes[node]
+                .children
+                .iter()
+                .max_by(|a, b| nodes[**a].uct(parent_visits).total_cmp(&nodes[**b].uct(parent_visits)))
+                .unwrap();
+            position.apply(nodes[node].mv.unwrap()).expect("tree moves are legal");
+        }
+        // expansion of one untried move
+        if !nodes[node].untried.is_empty() {
+            let untried = &mut nodes[node].untried;
+            let mv = untried.swap_remove(rng.below(untried.len()));
+            position.apply(mv).expect("untried moves are legal");
+            let child = nodes.len();
+            nodes.push(Node::new(&position, Some(mv), Some(node)));
+            nodes[node].children.push(child);
+            node = child;
+        }
+        // playout
+        loop {
+            let moves = position.legal_moves();
+            if moves.is_empty() {
+                break;
+            }
+            position.apply(moves[rng.below(moves.len())]).expect("legal moves are legal");
+        }
+        // backpropagation
+        let mut current = Some(node);
+        while let Some(index) = current {
+            nodes[index].visits += 1;
+            nodes[index].score += score(&position, nodes[index].player);
+            current = nodes[index].parent;
+        }
+    }
+    nodes[0]
+        .children
+        .iter()
+        .max_by_key(|child| nodes[**child].visits)
+        .and_then(|child| nodes[*child].mv)
+        .or_else(|| moves.first().copied())
+}
@@ -0,0 +1,203 @@
+use std::{sync::{atomic::{AtomicBool, Ordering}, Arc}, time::{Duration, Instant, SystemTime}};
+
+use bevy::{prelude::*, tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task}};
+use bevy_egui::{egui::{self, Align2}, EguiContexts};
+
+use crate::{grid_cell::{indices_to_path, Cell}, network::{client::{occupy_cell, CurrentGame}, GameEvent, ReceiveEventQueue, StartClient}, rules::{Game, Move}, GameState};
+pub mod alpha_beta;
+pub mod mcts;
+
+use alpha_beta::Difficulty;
+
+pub struct BotPlugin;
+
+impl Plugin for BotPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BotSettings>()
+        .init_resource::<BotSearch>()
+        .add_systems(Update, (
+            poll_bot_search.before(occupy_cell),
+            start_bot_search.after(occupy_cell),
+            thinking_ui_system,
+        ).run_if(in_state(GameState::InGame)))
+        .add_systems(OnExit(GameState::InGame), cancel_bot_search);
+    }
+}
+
+/// How long the bot searches for a move
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Budget {
+    /// Fixed amount of playouts, same strength on any computer
+    Iterations(u32),
+    /// Time limit per move
+    Time(Duration),
+}
+
+impl Budget {
+    /// True if search that made given iterations since `started` has to stop
+    pub fn is_spent(&self, iterations: u32, started: Instant) -> bool {
+        match self {
+            Budget::Iterations(limit) => iterations >= *limit,
+            Budget::Time(limit) => started.elapsed() >= *limit,
+        }
+    }
+}
+
+/// Small xorshift random generator, bots don't need anything stronger
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // zero state would only produce zeros
+        Rng(seed | 1)
+    }
+    /// Generator seeded from current time
+    pub fn from_time() -> Rng {
+        let nanos = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_nanos() as u64);
+        Rng::new(nanos)
+    }
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+    /// Random number in `0..limit`, limit must not be zero
+    pub fn below(&mut self, limit: usize) -> usize {
+        (self.next_u64() % limit as u64) as usize
+    }
+}
+
+/// Search the computer opponent uses
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BotKind {
+    /// Monte-Carlo tree search, gets stronger with bigger budget
+    Mcts,
+    /// Alpha-beta search, plays the same way at each difficulty
+    AlphaBeta,
+}
+
+/// Search settings of the computer opponent, chosen in menu
+#[derive(Resource, Clone, Copy)]
+pub struct BotSettings {
+    pub kind: BotKind,
+    /// Budget of MCTS bot, alpha-beta bot is limited by the time its search is given instead
+    pub budget: Budget,
+    /// Difficulty of alpha-beta bot
+    pub difficulty: Difficulty,
+}
+
+impl Default for BotSettings {
+    fn default() -> Self {
+        BotSettings {
+            kind: BotKind::Mcts,
+            budget: Budget::Iterations(2000),
+            difficulty: Difficulty::Medium,
+        }
+    }
+}
+
+impl BotSettings {
+    /// Move the configured bot makes in the position, None if game is over or search was stopped
+    ///
+    /// Alpha-beta search returns its deepest finished search after `time_limit`, MCTS keeps to its budget
+    pub fn best_move(&self, game: &Game, rng: &mut Rng, stop: &AtomicBool, time_limit: Duration) -> Option<Move> {
+        match self.kind {
+            BotKind::Mcts => mcts::best_move(game, self.budget, rng, stop),
+            BotKind::AlphaBeta => alpha_beta::best_move(game, self.difficulty, rng, stop, Instant::now() + time_limit),
+        }
+    }
+}
+
+/// Event clicking the cell of a move, so bot moves go the same way as moves of players
+pub fn move_event(mv: Move, game: &Game) -> GameEvent {
+    GameEvent::ClickedCell(Cell {
+        path: indices_to_path(mv.path(), &game.config()),
+        state: game.to_move().into(),
+    })
+}
+
+/// Search running on a background thread
+#[derive(Resource, Default)]
+pub struct BotSearch {
+    task: Option<Task<Option<Move>>>,
+    /// Set to make the running search return early
+    stop: Arc<AtomicBool>,
+}
+
+impl BotSearch {
+    /// True while the computer is thinking
+    pub fn is_running(&self) -> bool {
+        self.task.is_some()
+    }
+    /// Starts searching a copy of the game, search that was running is stopped
+    ///
+    /// `time_limit` only limits alpha-beta search, see `BotSettings::best_move`
+    pub fn start(&mut self, settings: BotSettings, game: Game, time_limit: Duration) {
+        self.cancel();
+        let stop = Arc::new(AtomicBool::new(false));
+        self.stop = stop.clone();
+        self.task = Some(AsyncComputeTaskPool::get().spawn(async move {
+            settings.best_move(&game, &mut Rng::from_time(), &stop, time_limit)
+        }));
+    }
+    /// Result of finished search, it is only returned once
+    pub fn poll(&mut self) -> Option<Option<Move>> {
+        let result = block_on(future::poll_once(self.task.as_mut()?))?;
+        self.task = None;
+        Some(result)
+    }
+    /// Stops running search, its result is never returned
+    pub fn cancel(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.task = None;
+    }
+}
+
+/// Starts search on a copy of the game when it is computer's turn
+fn start_bot_search(
+    game: Res<CurrentGame>,
+    start_client: Res<State<StartClient>>,
+    settings: Res<BotSettings>,
+    mut search: ResMut<BotSearch>,
+) {
+    let StartClient::Computer(human) = start_client.get() else {
+        return;
+    };
+    if search.is_running() || game.0.to_move() == *human || game.0.outcome().is_some() {
+        return;
+    }
+    search.start(*settings, game.0.clone(), settings.difficulty.time_limit());
+}
+
+/// Sends move of finished search the same way moves of players go
+fn poll_bot_search(
+    game: Res<CurrentGame>,
+    mut search: ResMut<BotSearch>,
+    mut received_event_queue: ResMut<ReceiveEventQueue>,
+) {
+    if let Some(Some(mv)) = search.poll() {
+        received_event_queue.0.push_back(move_event(mv, &game.0));
+    }
+}
+
+/// Stops search of abandoned game
+fn cancel_bot_search(mut search: ResMut<BotSearch>) {
+    search.cancel();
+}
+
+fn thinking_ui_system(mut contexts: EguiContexts, search: Res<BotSearch>) {
+    if !search.is_running() {
+        return;
+    }
+    egui::Area::new(egui::Id::new("bot thinking")).anchor(Align2::CENTER_TOP, [0., 10.]).show(contexts.ctx_mut(), |ui| {
+        egui::Frame::popup(ui.style()).show(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.label("Computer is thinking…");
+            });
+        });
+    });
+}
@@ -3,6 +3,8 @@ use std::cmp::min;
 use bevy::{prelude::*, render::camera::ScalingMode, transform};
 use bevy::diagnostic::{FrameTimeDiagnosticsPlugin,LogDiagnosticsPlugin};
 // use bevy_lunex::prelude::MainUi;
+use crate::grid_cell::main_grid_pixels;
+use crate::rules::GridConfig;
 
 pub struct CameraPlugin;
 
@@ -30,13 +32,15 @@ fn add_camera(mut commands: Commands){
 
 fn resize_camera(
     mut camera: Query<&mut OrthographicProjection, With<Camera2d>>,
-    window: Query<&Window>
+    window: Query<&Window>,
+    config: Res<GridConfig>,
 ){
     let window = window.get_single().unwrap();
     let min = min(window.width() as i32, window.height() as i32);
-    if min < 900 {
+    let grid_size = main_grid_pixels(&config);
+    if (min as f32) < grid_size {
         let mut camera = camera.get_single_mut().unwrap(); 
-        camera.scaling_mode = ScalingMode::WindowSize((min as f32) / 900.);
+        camera.scaling_mode = ScalingMode::WindowSize((min as f32) / grid_size);
     }
     
 }
\ No newline at end of file
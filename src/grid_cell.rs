@@ -1,14 +1,21 @@
-use bevy::{ecs::query, math::vec3, prelude::*, window::PrimaryWindow};
+use bevy::{ecs::query, math::vec3, prelude::*, render::{render_asset::RenderAssetUsages, render_resource::{Extent3d, TextureDimension, TextureFormat}}, window::PrimaryWindow};
 use serde::{Deserialize, Serialize};
-use crate::network::client::{AvailableGrid,Winner};
+use crate::network::client::{AvailableGrid,BoardTally,CurrentGame,ShownGame,Winner};
+use crate::rules::{BoardState, FinishedBoardRule, GridConfig, Move, Player, Rules};
+use crate::network::StartClient;
 use crate::GameState;
 
 pub struct  CellGridPlugin;
 
 impl Plugin for CellGridPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, (initialize_cell_creator,spawn_grid,finish_grid_initializing).chain().run_if(in_state(GameState::StartingGame)))
-            .add_systems(Update, (update_cell_textures,validate_gridcells,validate_main_grid).run_if(in_state(GameState::InGame)));
+        app.init_resource::<GridConfig>()
+            .init_resource::<Rules>()
+            .add_systems(Update, (initialize_cell_creator,spawn_grid,finish_grid_initializing).chain().run_if(in_state(GameState::StartingGame)))
+            .add_systems(Update, (update_cell_textures,mirror_cells,validate_gridcells).run_if(in_state(GameState::InGame).or_else(in_state(GameState::Replaying))))
+            .add_systems(Update, validate_main_grid.run_if(in_state(GameState::InGame)))
+            .add_systems(Startup, configure_line_gizmos)
+            .add_systems(Update, draw_winning_lines.run_if(in_state(GameState::InGame).or_else(in_state(GameState::Replaying))));
     }
 }
 
@@ -20,11 +27,37 @@ pub enum CellState {
     Completed
 }
 
+impl CellState {
+    /// Player this state belongs to, None for empty and completed cells
+    pub fn player(&self) -> Option<Player> {
+        match self {
+            CellState::X => Some(Player::X),
+            CellState::O => Some(Player::O),
+            _ => None,
+        }
+    }
+}
+
+impl From<Player> for CellState {
+    fn from(player: Player) -> Self {
+        match player {
+            Player::X => CellState::X,
+            Player::O => CellState::O,
+        }
+    }
+}
+
+impl Resource for GridConfig {}
+impl Resource for Rules {}
+
 #[derive(Component)]
 pub struct Grid;
 /// Root grid
 #[derive(Component)]
 struct MainGrid;
+/// Big translucent mark drawn over a won grid, cells under it stay visible
+#[derive(Component)]
+struct WonOverlay;
 
 #[derive(Bundle)]
 struct GridBundle{
@@ -34,14 +67,64 @@ struct GridBundle{
 
 #[derive(Component,Serialize,Deserialize,Clone)]
 pub struct Cell {
-    /// Position relative to grid center 
-    pub pos: IVec2,
-    /// grid positon relative to root grid center
-    pub grid_pos:Option<IVec2>,
+    /// Positions in every grid from root grid down to this cell, (0,0) is the bottom left cell
+    pub path: Vec<IVec2>,
     /// Current cell state (X, O or empty)
     pub state: CellState,
 }
 
+impl Cell {
+    /// Move that occupies this cell, None for grid cells and positions outside of the grid
+    pub fn to_move(&self, config: &GridConfig) -> Option<Move> {
+        let inside = self.path.iter().enumerate().all(|(level, pos)| {
+            let size = config.level_size(level) as i32;
+            (0..size).contains(&pos.x) && (0..size).contains(&pos.y)
+        });
+        (self.path.len() == config.depth && inside).then(|| Move::new(&path_to_indices(&self.path, config)))
+    }
+}
+
+/// Converts positions of a path to indices used by game model
+pub fn path_to_indices(path: &[IVec2], config: &GridConfig) -> Vec<usize> {
+    path.iter().enumerate().map(|(level, pos)| pos_to_index(*pos, config.level_size(level))).collect()
+}
+
+/// Converts indices used by game model to positions of a path
+pub fn indices_to_path(path: &[usize], config: &GridConfig) -> Vec<IVec2> {
+    path.iter().enumerate().map(|(level, index)| index_to_pos(*index, config.level_size(level))).collect()
+}
+
+/// Converts position in grid to index used by game model
+pub fn pos_to_index(pos: IVec2, size: usize) -> usize {
+    pos.y as usize * size + pos.x as usize
+}
+
+/// Converts index used by game model to position in grid
+pub fn index_to_pos(index: usize, size: usize) -> IVec2 {
+    IVec2 {
+        x: (index % size) as i32,
+        y: (index / size) as i32,
+    }
+}
+
+/// Size of one cell sprite in pixels
+pub const CELL_PIXELS: f32 = 100.;
+
+/// Size of grid sprite on given nesting level in pixels, level 0 is the root grid
+pub fn grid_pixels(config: &GridConfig, level: usize) -> f32 {
+    (level..config.depth).map(|level| config.level_size(level) as f32).product::<f32>() * CELL_PIXELS
+}
+
+/// Size of main grid sprite in pixels
+pub fn main_grid_pixels(config: &GridConfig) -> f32 {
+    grid_pixels(config, 0)
+}
+
+/// Offset of a sprite from the grid center, so grids of any size are centered
+fn grid_offset(pos: IVec2, size: usize, step: f32) -> Vec2 {
+    (pos.as_vec2() - (size as f32 - 1.) / 2.) * step
+}
+
 /// True if cell texture must be updated
 #[derive(Component)]
 pub struct UpdateState(pub bool);
@@ -57,51 +140,56 @@ struct CellBundle {
 /// Spawns all needed Entities
 fn spawn_grid(mut commands: Commands, cell_spawner:Res<GridCellCreator>) {
     info!("spawning grid");
-    let grid: Entity = commands.spawn( (cell_spawner.new_grid(IVec2 { x: 0, y: 0 }),MainGrid)).id();
-    for grid_id in 0..=8 {
-        info!("adding grid_cell: {}",grid_id);
-        let grid_pos = IVec2 {
-            x: (grid_id % 3) - 1,
-            y: grid_id / 3 - 1,
-        };
-        let cell_grid = commands.spawn((
-            cell_spawner.new_grid(grid_pos),
-            Cell {
-                grid_pos:None,
-                pos: grid_pos,
-                state: CellState::Empty
-            }
-        )).id();
-        commands.entity(grid).add_child(cell_grid);
-        for cell_id in 0..=8 {
-            info!("     adding cell {}",cell_id);
-            let pos = IVec2 {
-                x: (cell_id % 3) - 1,
-                y: cell_id / 3 - 1,
-            };
-            let cell = commands.spawn(cell_spawner.new_cell(CellState::Empty, pos,Some(grid_pos))).id();
-            commands.entity(cell_grid).add_child(cell);
+    let grid: Entity = commands.spawn( (cell_spawner.new_grid(&[]),MainGrid)).id();
+    spawn_grid_cells(&mut commands, &cell_spawner, grid, Vec::new());
+}
+
+/// Spawns cells of the grid with given path, cells that are grids themselves get their own cells
+fn spawn_grid_cells(commands: &mut Commands, cell_spawner: &GridCellCreator, grid: Entity, path: Vec<IVec2>) {
+    let size = cell_spawner.config.level_size(path.len());
+    for cell_id in 0..size * size {
+        let mut cell_path = path.clone();
+        cell_path.push(index_to_pos(cell_id, size));
+        if cell_path.len() == cell_spawner.config.depth {
+            let cell = commands.spawn(cell_spawner.new_cell(CellState::Empty, cell_path)).id();
+            commands.entity(grid).add_child(cell);
+        } else {
+            let cell_grid = commands.spawn((
+                cell_spawner.new_grid(&cell_path),
+                Cell {
+                    path: cell_path.clone(),
+                    state: CellState::Empty
+                }
+            )).id();
+            let overlay = commands.spawn((cell_spawner.new_overlay(cell_path.len()), WonOverlay)).id();
+            commands.entity(cell_grid).add_child(overlay);
+            commands.entity(grid).add_child(cell_grid);
+            spawn_grid_cells(commands, cell_spawner, cell_grid, cell_path);
         }
     }
 }
 
-/// Debug stuff + main grid sprite resize
+/// Sprites of every grid except the main one
+type InnerGridQuery<'w, 's> = Query<'w, 's, (&'static mut Sprite, &'static Cell), (With<Grid>, Without<MainGrid>)>;
+
+/// Grid sprites resize
 fn finish_grid_initializing(
-    query: Query<&Transform,With<Cell>>,
-    mut grid_q: Query<&mut Sprite,(With<Grid>,Without<MainGrid>)>,
+    mut grid_q: InnerGridQuery,
     mut main_grid_q:Query<&mut Sprite,With<MainGrid>>,
+    config: Res<GridConfig>,
+    start_client: Res<State<StartClient>>,
     mut next_game_stat: ResMut<NextState<GameState>>,
 ){
     info!("finishing grid creation");
-    for transform in &query{
-        info!("cell here: {:?}",transform.translation);
-    }
-    for mut sprite in &mut grid_q{
-        sprite.custom_size = Some(Vec2 { x: 300., y: 300. });
+    for (mut sprite,cell) in &mut grid_q{
+        sprite.custom_size = Some(Vec2::splat(grid_pixels(&config, cell.path.len())));
     }
     let mut sprite = main_grid_q.single_mut();
-    sprite.custom_size = Some(Vec2{x:900.,y:900.});
-    next_game_stat.set(GameState::InGame);
+    sprite.custom_size = Some(Vec2::splat(main_grid_pixels(&config)));
+    next_game_stat.set(match start_client.get() {
+        StartClient::Replay => GameState::Replaying,
+        _ => GameState::InGame,
+    });
 }
 
 /// Updates cells flagged with update flag
@@ -118,139 +206,164 @@ fn update_cell_textures(
         }
     }
     for (mut texture,cell) in &mut gridcell_query{
-        *texture = match cell.state {
-            CellState::X => cell_spawner.get_texture(cell.state),
-            CellState::O => cell_spawner.get_texture(cell.state),
-            _ => {
-                if let Some(pos) = next_grid_pos.0 {
-                    if pos == cell.pos{
-                        cell_spawner.next_grid_texture.clone()
-                    }
-                    else {
-                        cell_spawner.grid_texture.clone()
-                    }
-                }else {
-                    cell_spawner.grid_texture.clone()
-                }
-            },
+        *texture = cell_spawner.get_grid_texture(cell.path.len(), next_grid_pos.0 == cell.path);
+    }
+}
+
+/// Copies cell owners from game model to cell entities
+///
+/// Cells inside grids that can't be played in anymore are dimmed
+fn mirror_cells(
+    mut cell_q: Query<(&mut Cell, &mut UpdateState, &mut Sprite), Without<Grid>>,
+    game: Res<ShownGame>,
+){
+    if !game.is_changed() {
+        return;
+    }
+    let free_move = game.0.rules().finished_board == FinishedBoardRule::FreeMove;
+    for (mut cell, mut update, mut sprite) in &mut cell_q {
+        let Some(mv) = cell.to_move(&game.0.config()) else {
+            continue;
         };
+        let closed = (1..mv.path().len()).any(|len| match game.0.board_state(&mv.path()[..len]) {
+            BoardState::Open => false,
+            BoardState::Won(_) => free_move,
+            BoardState::Drawn => true,
+        });
+        sprite.color = match closed {
+            true => Color::rgba(1., 1., 1., 0.35),
+            false => Color::WHITE,
+        };
+        let state = game.0.cell(mv.path()).map_or(CellState::Empty, CellState::from);
+        if cell.state != state {
+            cell.state = state;
+            *update = UpdateState(true);
+        }
     }
 }
 
+/// Copies nested board states from game model to grid entities
+///
+/// Won grids get the mark of the winner over their cells
 fn validate_gridcells(
-    mut gridcells_q: Query<(Entity,&mut Cell,&Children),With<Grid>>,
-    cell_q: Query<&Cell,Without<Grid>>,
-    mut commands: Commands,
-    // mut main_grid: Query<(&Grid,&Children),With<MainGrid>>,
+    mut gridcells_q: Query<(&mut Cell,&mut Sprite,&Children),With<Grid>>,
+    mut overlay_q: Query<(&mut Handle<Image>,&mut Visibility),With<WonOverlay>>,
+    cell_spawner: Res<GridCellCreator>,
+    game: Res<ShownGame>,
 ){
-    for (grid,mut cell, children) in &mut gridcells_q {
-        if cell.state != CellState::Empty {
+    if !game.is_changed() {
+        return;
+    }
+    for (mut cell, mut sprite, children) in &mut gridcells_q {
+        let state = game.0.board_state(&path_to_indices(&cell.path, &game.0.config()));
+        let cell_state = match state {
+            BoardState::Open => CellState::Empty,
+            BoardState::Won(owner) => owner.into(),
+            BoardState::Drawn => CellState::Completed,
+        };
+        if cell.state == cell_state {
             continue;
         }
-        let mut state_map = [[CellState::Empty;3];3];
-        for child in children {
-            let cell = cell_q.get(*child);
-            if let Ok(cell) = cell{
-                state_map[(cell.pos.x + 1) as usize][(cell.pos.y + 1) as usize] = cell.state;
-            }
-        }
-        for i in 0..3 {
-            let mut vertical_count = (0,state_map[i][0]);
-            let mut horizontal_count = (0,state_map[0][i]);
-            let mut diagonal_count = (0,state_map[1][1]);
-            let mut diagonal_count_rev = (0,state_map[1][1]);
-
-            for j in 0..3 {
-                if state_map[i][j] != CellState::Empty && state_map[i][j] == vertical_count.1 {
-                    vertical_count.0 +=1;
-                }
-                if state_map[j][i] != CellState::Empty && state_map[j][i] == horizontal_count.1 {
-                    horizontal_count.0 +=1;
-                }
-                if state_map[j][j] != CellState::Empty && state_map[j][j] == diagonal_count.1 {
-                    diagonal_count.0 +=1;
-                }
-                if state_map[j][2-j] != CellState::Empty && state_map[j][2-j] == diagonal_count_rev.1 {
-                    diagonal_count_rev.0 +=1;
-                }
-            }
-            if vertical_count.0 == 3 {
-                cell.state = vertical_count.1;
-
-            } else if horizontal_count.0 == 3 {
-                cell.state = horizontal_count.1;
-            } else if diagonal_count.0 == 3 {
-                cell.state = diagonal_count.1;
-            } else if diagonal_count_rev.0 == 3 {
-                cell.state = diagonal_count_rev.1;
-            }
-            if cell.state != CellState::Empty {
-                info!("filled gridcell");
-                break;
-            }
+        cell.state = cell_state;
+        sprite.color = Color::WHITE;
+        match state {
+            BoardState::Open => (),
+            BoardState::Won(_) => info!("filled gridcell"),
+            BoardState::Drawn => {
+                info!("drawn gridcell");
+                sprite.color = Color::GRAY;
+            },
         }
-        if cell.state != CellState::Empty{
-            for child in children {
-                commands.entity(*child).despawn();
+        for child in children {
+            if let Ok((mut texture, mut visibility)) = overlay_q.get_mut(*child) {
+                *texture = cell_spawner.get_texture(cell_state);
+                *visibility = match state {
+                    BoardState::Won(_) => Visibility::Inherited,
+                    _ => Visibility::Hidden,
+                };
             }
         }
     }
 }
 
+/// Strike-through lines are thick enough to be seen over cell textures
+fn configure_line_gizmos(mut config_store: ResMut<GizmoConfigStore>) {
+    let (config, _) = config_store.config_mut::<DefaultGizmoConfigGroup>();
+    config.line_width = 6.;
+}
+
+/// Draws a line through marks that won each grid, the line that won the game is highlighted
+fn draw_winning_lines(
+    grid_q: Query<(&GlobalTransform,Option<&Cell>),With<Grid>>,
+    game: Res<ShownGame>,
+    mut gizmos: Gizmos,
+){
+    let config = game.0.config();
+    for (transform, cell) in &grid_q {
+        let path = cell.map_or(Vec::new(), |cell| cell.path.clone());
+        let Some(line) = game.0.line(&path_to_indices(&path, &config)) else {
+            continue;
+        };
+        let size = config.level_size(path.len());
+        let step = grid_pixels(&config, path.len() + 1);
+        let start = grid_offset(index_to_pos(line.start, size), size, step);
+        let end = grid_offset(index_to_pos(line.end, size), size, step);
+        // line goes a bit past centers of end cells so it covers the marks
+        let overshoot = (end - start).normalize_or_zero() * step * 0.3;
+        let center = transform.translation().truncate();
+        let color = match (path.is_empty(), line.owner) {
+            (true, _) => Color::GOLD,
+            (false, Player::X) => Color::RED,
+            (false, Player::O) => Color::BLUE,
+        };
+        gizmos.line_2d(center + start - overshoot, center + end + overshoot, color);
+    }
+}
+
+/// Copies game result and won grids count from game model
 fn validate_main_grid(
-    mut main_grid: Query<(Entity,&Children),With<MainGrid>>,
-    cell_q: Query<&Cell,With<Grid>>,
-    mut commands: Commands,
-    mut winner: ResMut<Winner>
+    game: Res<CurrentGame>,
+    mut winner: ResMut<Winner>,
+    mut tally: ResMut<BoardTally>,
 ){
-    let (mut grid_entity, mut children) = main_grid.get_single_mut().unwrap();
-    let mut state_map = [[CellState::Empty;3];3];
-    for child in children {
-        let cell = cell_q.get(*child);
-        if let Ok(cell) = cell{
-            state_map[(cell.pos.x + 1) as usize][(cell.pos.y + 1) as usize] = cell.state;
-        }
+    if !game.is_changed() {
+        return;
     }
-    for i in 0..3 {
-        let mut vertical_count = (0,state_map[i][0]);
-        let mut horizontal_count = (0,state_map[0][i]);
-        let mut diagonal_count = (0,state_map[1][1]);
-        let mut diagonal_count_rev = (0,state_map[1][1]);
-
-        for j in 0..3 {
-            if state_map[i][j] != CellState::Empty && state_map[i][j] == vertical_count.1 {
-                vertical_count.0 +=1;
-            }
-            if state_map[j][i] != CellState::Empty && state_map[j][i] == horizontal_count.1 {
-                horizontal_count.0 +=1;
-            }
-            if state_map[j][j] != CellState::Empty && state_map[j][j] == diagonal_count.1 {
-                diagonal_count.0 +=1;
-            }
-            if state_map[j][2-j] != CellState::Empty && state_map[j][2-j] == diagonal_count_rev.1 {
-                diagonal_count_rev.0 +=1;
-            }
-        }
-        if vertical_count.0 == 3 {
-            winner.0 = Some(vertical_count.1)
-        } else if horizontal_count.0 == 3 {
-            winner.0 = Some(horizontal_count.1)
-        } else if diagonal_count.0 == 3 {
-            winner.0 = Some(diagonal_count.1)
-        } else if diagonal_count_rev.0 == 3 {
-            winner.0 = Some(diagonal_count_rev.1)
-        }
-        if winner.0.is_some() {
-            info!("filled main grid");
-            break;
-        }
+    *tally = BoardTally {
+        x: game.0.won_boards(Player::X),
+        o: game.0.won_boards(Player::O),
+    };
+    if winner.0.is_none() && game.0.outcome().is_some() {
+        info!("filled main grid");
     }
+    winner.0 = game.0.outcome();
 }
 
 /// Creates resource used to spawn cells more efficiently 
-fn initialize_cell_creator(asset_server:Res<AssetServer>,mut commands: Commands){
+fn initialize_cell_creator(asset_server:Res<AssetServer>,mut images: ResMut<Assets<Image>>,config: Res<GridConfig>,mut commands: Commands){
     info!("initializing cell creator");
-    commands.insert_resource(GridCellCreator::new(&asset_server));
+    commands.insert_resource(GridCellCreator::new(&asset_server, &mut images, *config));
+}
+
+/// Creates transparent texture with lines between `size` x `size` cells, like grid.png
+fn lines_texture(images: &mut Assets<Image>, size: usize, color: [u8; 4]) -> Handle<Image> {
+    const CELL: usize = 32;
+    const LINE_WIDTH: usize = 2;
+    let side = size * CELL;
+    let on_line = |v: usize| (1..size).any(|line| v.abs_diff(line * CELL) < LINE_WIDTH);
+    let mut image = Image::new_fill(
+        Extent3d { width: side as u32, height: side as u32, depth_or_array_layers: 1 },
+        TextureDimension::D2,
+        &[0, 0, 0, 0],
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    );
+    for (pixel, data) in image.data.chunks_mut(4).enumerate() {
+        if on_line(pixel % side) || on_line(pixel / side) {
+            data.copy_from_slice(&color);
+        }
+    }
+    images.add(image)
 }
 
 /// Creates cells and grids 
@@ -259,8 +372,13 @@ struct GridCellCreator{
     pub x_texture:Handle<Image>,
     pub o_texture:Handle<Image>,
     pub empty_texture:Handle<Image>,
+    /// Lines of grids that hold cells
     pub grid_texture:Handle<Image>,
     pub next_grid_texture:Handle<Image>,
+    /// Lines of grids that hold other grids
+    pub outer_grid_texture:Handle<Image>,
+    pub next_outer_grid_texture:Handle<Image>,
+    config: GridConfig,
 }
 
 impl GridCellCreator {
@@ -273,52 +391,83 @@ impl GridCellCreator {
             CellState::Completed => self.empty_texture.clone(),
         }
     }
+    /// Get lines texture for grid on given nesting level
+    fn get_grid_texture(&self,level:usize,is_next:bool) -> Handle<Image> {
+        match (level + 1 == self.config.depth, is_next) {
+            (true, false) => self.grid_texture.clone(),
+            (true, true) => self.next_grid_texture.clone(),
+            (false, false) => self.outer_grid_texture.clone(),
+            (false, true) => self.next_outer_grid_texture.clone(),
+        }
+    }
     /// Creates new GridCellCreator
-    fn new(asset_server: &Res<AssetServer>) -> GridCellCreator{
+    ///
+    /// Grid textures from assets are drawn for 3x3 grids, other sizes get generated ones
+    fn new(asset_server: &Res<AssetServer>, images: &mut Assets<Image>, config: GridConfig) -> GridCellCreator{
+        let mut grid_textures = |size: usize| match size {
+            3 => (asset_server.load("grid.png"), asset_server.load("next_grid.png")),
+            size => (lines_texture(images, size, [0, 0, 0, 255]), lines_texture(images, size, [0, 200, 100, 255])),
+        };
+        let (grid_texture, next_grid_texture) = grid_textures(config.board_size);
+        let (outer_grid_texture, next_outer_grid_texture) = grid_textures(config.outer_size);
         GridCellCreator{
             x_texture: asset_server.load("cell_X.png"),
             o_texture: asset_server.load("cell_O.png"),
             empty_texture: asset_server.load("cell_empty.png"),
-            grid_texture: asset_server.load("grid.png"),
-            next_grid_texture: asset_server.load("next_grid.png"),
+            grid_texture,
+            next_grid_texture,
+            outer_grid_texture,
+            next_outer_grid_texture,
+            config,
         }
     }
     /// Creates CellBundle 
-    fn new_cell(&self,state:CellState,pos:IVec2,grid_pos:Option<IVec2>)-> CellBundle{
+    fn new_cell(&self,state:CellState,path:Vec<IVec2>)-> CellBundle{
+        let pos = *path.last().unwrap();
         CellBundle {
-            cell: Cell { pos,grid_pos,state:CellState::Empty},
+            cell: Cell { path,state:CellState::Empty},
             sprite: SpriteBundle {
-                transform: Transform::from_translation(Vec3 {
-                    x: pos.x as f32 * 100.,
-                    y: pos.y as f32 * 100.,
-                    z: -1.,
-                }),
-                texture: match state {
-                    CellState::X => self.x_texture.clone(),
-                    CellState::O => self.o_texture.clone(),
-                    CellState::Empty => self.empty_texture.clone(),
-                    CellState::Completed => self.empty_texture.clone(),
-                    
-                },
+                transform: Transform::from_translation(
+                    grid_offset(pos, self.config.board_size, CELL_PIXELS).extend(-1.)
+                ),
+                texture: self.get_texture(state),
                 ..default()
             },
             update_state:UpdateState(false)
         }
     }
 
-    /// Creates GridBundle
-    fn new_grid(&self,pos:IVec2)->GridBundle{
+    /// Creates hidden winner mark covering grid on given nesting level, it is drawn over cells and lines
+    fn new_overlay(&self,level:usize)->SpriteBundle{
+        SpriteBundle{
+            transform: Transform::from_translation(Vec3::new(0., 0., 1.)),
+            texture: self.empty_texture.clone(),
+            sprite: Sprite {
+                color: Color::rgba(1., 1., 1., 0.6),
+                custom_size: Some(Vec2::splat(grid_pixels(&self.config, level))),
+                ..default()
+            },
+            visibility: Visibility::Hidden,
+            ..default()
+        }
+    }
+    /// Creates GridBundle, empty path is the root grid
+    fn new_grid(&self,path:&[IVec2])->GridBundle{
+        let translation = match path.split_last() {
+            Some((pos, parent)) => grid_offset(
+                *pos,
+                self.config.level_size(parent.len()),
+                grid_pixels(&self.config, path.len()),
+            ).extend(-1.),
+            None => Vec3::new(0., 0., -1.),
+        };
         GridBundle{
             grid: Grid,
             obj: SpriteBundle{
-                transform: Transform::from_translation(Vec3 {
-                    x: pos.x as f32 * 300.,
-                    y: pos.y as f32 * 300.,
-                    z: -1.,
-                }),
-                texture: self.grid_texture.clone(),
+                transform: Transform::from_translation(translation),
+                texture: self.get_grid_texture(path.len(), false),
                 ..default()
             },
         }
     }
-}
\ No newline at end of file
+}
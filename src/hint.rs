@@ -0,0 +1,148 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy_egui::egui;
+
+use crate::{bot::{alpha_beta::Difficulty, BotKind, BotSearch, BotSettings, Budget}, grid_cell::{indices_to_path, Cell, Grid, CELL_PIXELS}, network::{client::CurrentGame, GameEvent, SendEventQueue}, rules::{Game, Move, Player}, GameState};
+
+pub struct HintPlugin;
+
+impl Plugin for HintPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<HintCount>()
+        .init_resource::<Hint>()
+        .add_systems(Update, (poll_hint_search, draw_hint).chain().run_if(in_state(GameState::InGame)))
+        .add_systems(OnExit(GameState::InGame), clear_hints);
+    }
+}
+
+/// Engine used for hints, the strongest deterministic one
+const HINT_ENGINE: BotSettings = BotSettings {
+    kind: BotKind::AlphaBeta,
+    // only used by MCTS
+    budget: Budget::Iterations(2000),
+    difficulty: Difficulty::Hard,
+};
+/// Time hint search gets, it is cut short on big boards
+const HINT_TIME: Duration = Duration::from_secs(2);
+
+/// Hints used in this game by each player
+#[derive(Resource, Default)]
+pub struct HintCount {
+    pub x: usize,
+    pub o: usize,
+}
+
+impl HintCount {
+    pub fn used(&self, player: Player) -> usize {
+        match player {
+            Player::X => self.x,
+            Player::O => self.o,
+        }
+    }
+    pub fn add(&mut self, player: Player) {
+        match player {
+            Player::X => self.x += 1,
+            Player::O => self.o += 1,
+        }
+    }
+}
+
+/// Hint of this window, it is only shown in the position it was asked for
+#[derive(Resource, Default)]
+pub struct Hint {
+    search: BotSearch,
+    mv: Option<Move>,
+    /// Moves made before the position hint is for
+    history: Vec<Move>,
+    /// Moves made before the position of a hint waiting to be counted by server
+    pending: Option<Vec<Move>>,
+}
+
+impl Hint {
+    /// Starts search for the hint this window asked for, once its `HintUsed` came back accepted
+    pub fn accepted(&mut self, game: &Game) {
+        if self.pending.take().is_some_and(|history| history == game.history()) {
+            self.mv = None;
+            self.history = game.history().to_vec();
+            self.search.start(HINT_ENGINE, game.clone(), HINT_TIME);
+        }
+    }
+    /// Server refused something, a hint waiting for it is never going to be counted
+    pub fn refused(&mut self) {
+        self.pending = None;
+    }
+    /// True if hint was found for this position
+    fn is_for(&self, game: &Game) -> bool {
+        self.mv.is_some() && self.history == game.history()
+    }
+}
+
+/// Hint button and hint counts of both players, `player` is the side this window plays
+pub fn hint_ui(ui: &mut egui::Ui, player: Option<Player>, game: &Game, count: &HintCount, hint: &mut Hint, send_event_queue: &mut SendEventQueue) {
+    let hints = game.rules().hints_per_game;
+    if hints == 0 {
+        return;
+    }
+    ui.label(format!("Hints used: X {} - O {} (of {})", count.x, count.o, hints));
+    let Some(player) = player else {
+        return;
+    };
+    let left = hints.saturating_sub(count.used(player));
+    let waiting = hint.search.is_running() || hint.pending.is_some();
+    let can_ask = left > 0
+        && game.to_move() == player
+        && game.outcome().is_none()
+        && !waiting
+        && !hint.is_for(game);
+    ui.horizontal(|ui| {
+        if ui.add_enabled(can_ask, egui::Button::new(format!("Hint ({} left)", left))).clicked() {
+            // search starts once the hint is counted, server may refuse it
+            send_event_queue.0.push_back(GameEvent::HintUsed(player));
+            hint.pending = Some(game.history().to_vec());
+        }
+        if waiting {
+            ui.spinner();
+        }
+    });
+}
+
+fn poll_hint_search(mut hint: ResMut<Hint>) {
+    if let Some(mv) = hint.search.poll() {
+        hint.mv = mv;
+    }
+}
+
+/// Pulses the hinted cell until a move is made
+fn draw_hint(
+    cell_q: Query<(&Cell, &GlobalTransform), Without<Grid>>,
+    game: Res<CurrentGame>,
+    hint: Res<Hint>,
+    time: Res<Time>,
+    mut gizmos: Gizmos,
+) {
+    let Some(mv) = hint.mv else {
+        return;
+    };
+    if !hint.is_for(&game.0) {
+        return;
+    }
+    let path = indices_to_path(mv.path(), &game.0.config());
+    let Some((_, transform)) = cell_q.iter().find(|(cell, _)| cell.path == path) else {
+        return;
+    };
+    let pulse = 0.5 + 0.5 * (time.elapsed_seconds() * 4.).sin();
+    gizmos.rect_2d(
+        transform.translation().truncate(),
+        0.,
+        Vec2::splat(CELL_PIXELS * 0.9),
+        Color::rgba(1., 0.8, 0., 0.3 + 0.7 * pulse),
+    );
+}
+
+fn clear_hints(mut hint: ResMut<Hint>, mut count: ResMut<HintCount>) {
+    hint.search.cancel();
+    hint.mv = None;
+    hint.pending = None;
+    *count = HintCount::default();
+}
@@ -0,0 +1,31 @@
+//! Game shared by the windowed app and the headless `kit-tak-server`
+
+pub mod analysis;
+pub mod bot;
+pub mod camera;
+pub mod grid_cell;
+pub mod hint;
+pub mod network;
+pub mod menu;
+pub mod notation;
+pub mod replay;
+pub mod rules;
+pub mod saves;
+pub mod simulate;
+pub mod toast;
+
+use bevy::prelude::*;
+
+
+#[derive(States, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum GameState{
+    InMenu,
+    CreatingServer,
+    Connecting,
+    StartingGame,
+    InGame,
+    /// Stepping through a recorded game
+    Replaying,
+    FinishingGame
+    //...TODO
+}
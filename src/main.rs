@@ -1,26 +1,35 @@
-mod camera;
-mod grid_cell;
-mod network;
-mod menu;
 
 
 use std::net::{IpAddr, Ipv4Addr};
 
 use bevy::{ecs::entity, pbr::deferred, prelude::*, transform::commands};
 use bevy_quinnet::{client::{certificate::CertificateVerificationMode, connection::ClientEndpointConfiguration, QuinnetClient, QuinnetClientPlugin}, shared::channels::ChannelsConfiguration};
-use camera::CameraPlugin;
-use grid_cell::CellGridPlugin;
+use kit_tak::camera::CameraPlugin;
+use kit_tak::grid_cell::CellGridPlugin;
 use serde::{Deserialize, Serialize};
-use network::server::ServerPlugin;
-use network::client::ClientPlugin;
-use menu::MenuPlugin;
+use kit_tak::network::server::ServerPlugin;
+use kit_tak::network::client::ClientPlugin;
+use kit_tak::menu::MenuPlugin;
+use kit_tak::toast::ToastPlugin;
+use kit_tak::replay::ReplayPlugin;
+use kit_tak::bot::BotPlugin;
+use kit_tak::hint::HintPlugin;
+use kit_tak::analysis::AnalysisPlugin;
+use kit_tak::GameState;
 
 
 fn main() {
     // debug things for server creation
     let args = std::env::args().collect::<Vec<String>>();
+    if args.get(1).map(String::as_str) == Some("simulate") {
+        if let Err(error) = kit_tak::simulate::run(&args[2..]) {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+        return;
+    }
     // let username = &args[1];
-    let _app = App::new().insert_state(GameState::InMenu).add_plugins((DefaultPlugins,CameraPlugin,MenuPlugin,CellGridPlugin,ClientPlugin,ServerPlugin)).run();
+    let _app = App::new().insert_state(GameState::InMenu).add_plugins((DefaultPlugins,CameraPlugin,MenuPlugin,CellGridPlugin,ClientPlugin,ServerPlugin,ToastPlugin,ReplayPlugin,BotPlugin,HintPlugin,AnalysisPlugin)).run();
     // if username == "serv" {
     //     let _app = App::new().add_plugins((DefaultPlugins,CameraPlugin,CellGridPlugin,ClientPlugin::new(grid_cell::CellState::O),ServerPlugin)).run();
     // } else {
@@ -29,14 +38,3 @@ fn main() {
 
 }
 
-
-#[derive(States, Debug, Clone, PartialEq, Eq, Hash)]
-pub enum GameState{
-    InMenu,
-    CreatingServer,
-    Connecting,
-    StartingGame,
-    InGame,
-    FinishingGame
-    //...TODO
-}
\ No newline at end of file
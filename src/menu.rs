@@ -1,9 +1,9 @@
-use std::{net::Ipv4Addr, time::{Duration, SystemTime}};
+use std::{net::{Ipv4Addr, SocketAddrV4}, time::{Duration, SystemTime}};
 
 use bevy::{prelude::*, utils::info};
 use bevy_quinnet::server;
 use bevy_egui::{egui::{self, Color32}, EguiContexts, EguiPlugin};
-use crate::GameState;
+use crate::{network::client::LoadedGame, rules::{FinishedBoardRule, Game, GridConfig, Player, Rules}, bot::{alpha_beta::Difficulty, BotKind, BotSettings, Budget}, saves, toast::Toasts, GameState};
 
 pub struct MenuPlugin;
 
@@ -13,12 +13,22 @@ impl Plugin for MenuPlugin {
         .insert_state(ClientMode::Client("".to_string()))
         .insert_state(StartClient::None)
         .insert_state(FinishTimer::None)
+        .init_resource::<SavedGames>()
         .add_plugins(EguiPlugin)
+        .add_systems(OnEnter(GameState::InMenu), refresh_saved_games)
         .add_systems(Update, (menu_ui_system,start_system).run_if(in_state(GameState::InMenu)))
         .add_systems(Update, finish_game.run_if(in_state(GameState::FinishingGame)));
     }
 }
 
+/// Names of saved games, read from disk when menu opens and after saving instead of every frame
+#[derive(Resource, Default)]
+pub struct SavedGames(pub Vec<String>);
+
+fn refresh_saved_games(mut saved_games: ResMut<SavedGames>) {
+    saved_games.0 = saves::saved_games();
+}
+
 #[derive(States, Debug, Clone, PartialEq, Eq, Hash)]
 enum FinishTimer {
     None,
@@ -50,6 +60,9 @@ fn finish_game(
 
 #[derive(States, Debug, Clone, PartialEq, Eq, Hash)]
 enum ClientMode {
+    Local,
+    /// Human plays given player against the computer
+    Computer(Player),
     Server,
     Client(String)
 }
@@ -60,31 +73,76 @@ fn menu_ui_system(
     mut next_client_mode:ResMut<NextState<ClientMode>>, 
     current_start_state:Res<State<StartClient>>,
     mut next_start_state:ResMut<NextState<StartClient>>,
+    mut grid_config:ResMut<GridConfig>,
+    mut game_rules:ResMut<Rules>,
+    mut loaded_game:ResMut<LoadedGame>,
+    mut toasts:ResMut<Toasts>,
+    mut selected_save:Local<String>,
+    mut position_text:Local<String>,
+    mut bot_settings:ResMut<BotSettings>,
+    saved_games:Res<SavedGames>,
 ) {
-    let mut server_addr_string = String::new();
-    let mut is_server = match current_client_mode.get() {
-        ClientMode::Server => true,
-        ClientMode::Client(addr) => {
-            server_addr_string = addr.clone();
-            false
-        },
-    };
+    let mut config = *grid_config;
+    let mut rules = *game_rules;
+    let mut client_mode = current_client_mode.get().clone();
     egui::CentralPanel::default().show(contexts.ctx_mut(), |ui| {
         ui.vertical_centered(|ui|{
             ui.group(|ui|
             {
                 ui.label("Game Creation");
-                ui.checkbox(&mut is_server, "Is Server");
-                if !is_server{
-                    ui.text_edit_singleline(&mut server_addr_string);
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut client_mode, ClientMode::Local, "Local game");
+                    if ui.radio(matches!(client_mode, ClientMode::Computer(_)), "Play vs Computer").clicked() {
+                        client_mode = ClientMode::Computer(Player::X);
+                    }
+                    ui.radio_value(&mut client_mode, ClientMode::Server, "Is Server");
+                    if ui.radio(matches!(client_mode, ClientMode::Client(_)), "Join server").clicked() {
+                        client_mode = ClientMode::Client(String::new());
+                    }
+                });
+                match &mut client_mode {
+                    ClientMode::Client(server_addr_string) => {
+                        ui.add(egui::TextEdit::singleline(server_addr_string).hint_text(format!("127.0.0.1:{}", DEFAULT_PORT)));
+                        ui.label("Sizes and rules are chosen by server");
+                    },
+                    ClientMode::Computer(human) => {
+                        settings_ui(ui, &mut config, &mut rules);
+                        bot_settings_ui(ui, &mut bot_settings, human);
+                    },
+                    _ => settings_ui(ui, &mut config, &mut rules),
+                }
+                if client_mode == ClientMode::Local {
+                    ui.horizontal(|ui| {
+                        ui.label("Position");
+                        ui.text_edit_singleline(&mut *position_text);
+                        if ui.button("Start from position").clicked() {
+                            match Game::from_position(&position_text, rules) {
+                                Some(game) => {
+                                    config = game.config();
+                                    loaded_game.0 = Some(game);
+                                    next_start_state.set(StartClient::Local);
+                                },
+                                None => toasts.push("Can't read position"),
+                            }
+                        }
+                    });
                 }
-                if ui.button("Start").clicked(){
-                    match current_client_mode.get() {
+                if ui.button("Start").clicked() && config.is_valid(){
+                    match &client_mode {
+                        ClientMode::Local => {
+                            next_start_state.set(StartClient::Local)
+                        },
+                        ClientMode::Computer(human) => {
+                            next_start_state.set(StartClient::Computer(*human))
+                        },
                         ClientMode::Server => {
                             next_start_state.set(StartClient::Server)
                         },
                         ClientMode::Client(addr) => {
-                            next_start_state.set(match addr.parse::<Ipv4Addr>() {
+                            // port can be left out when server uses the default one
+                            let serv_addr = addr.parse::<SocketAddrV4>()
+                                .or_else(|_| addr.parse::<Ipv4Addr>().map(|ip| SocketAddrV4::new(ip, DEFAULT_PORT)));
+                            next_start_state.set(match serv_addr {
                                 Ok(serv_addr) => StartClient::Client(serv_addr),
                                 Err(_) => {
                                     StartClient::IncorrectAddress
@@ -97,18 +155,125 @@ fn menu_ui_system(
                     ui.colored_label(Color32::RED, "incorrect address");
                 }
             });
+            ui.group(|ui| {
+                ui.label("Saved games");
+                if saved_games.0.is_empty() {
+                    ui.label("No saved games yet");
+                    return;
+                }
+                egui::ComboBox::from_label("Save")
+                    .selected_text(selected_save.as_str())
+                    .show_ui(ui, |ui| {
+                        for name in &saved_games.0 {
+                            ui.selectable_value(&mut *selected_save, name.clone(), name);
+                        }
+                    });
+                ui.horizontal(|ui| {
+                    let load = ui.button("Load game").clicked();
+                    let replay = ui.button("Watch replay").clicked();
+                    if !load && !replay {
+                        return;
+                    }
+                    match saves::load_game(&selected_save).and_then(|record| record.to_game().map_err(|error| error.to_string())) {
+                        Ok(game) => {
+                            // loaded games are continued on this computer
+                            config = game.config();
+                            rules = game.rules();
+                            loaded_game.0 = Some(game);
+                            next_start_state.set(match replay {
+                                true => StartClient::Replay,
+                                false => StartClient::Local,
+                            });
+                        },
+                        Err(error) => toasts.push(format!("Can't load game: {}", error)),
+                    }
+                });
+            });
             
         });
         
     });
-    match is_server {
-        true => next_client_mode.set(ClientMode::Server),
-        false => next_client_mode.set(ClientMode::Client(server_addr_string)),
+    if config != *grid_config {
+        *grid_config = config;
+    }
+    if rules != *game_rules {
+        *game_rules = rules;
+    }
+    if client_mode != *current_client_mode.get() {
+        next_client_mode.set(client_mode);
     }
 }
 
 
-use crate::network::StartClient;
+/// Sizes and rule variants of the game server is going to host
+fn settings_ui(ui: &mut egui::Ui, config: &mut GridConfig, rules: &mut Rules) {
+    ui.add(egui::Slider::new(&mut config.board_size, 2..=6).text("Board size"));
+    ui.add(egui::Slider::new(&mut config.outer_size, 2..=6).text("Outer board size"));
+    // bigger boards can't be nested as deep, there would be too many cells to show
+    let max_depth = config.max_depth();
+    ui.add(egui::Slider::new(&mut config.depth, 1..=max_depth).text("Nesting depth"));
+    config.depth = config.depth.min(max_depth);
+    let max_win_length = match config.depth {
+        1 => config.board_size,
+        _ => config.board_size.min(config.outer_size),
+    };
+    ui.add(egui::Slider::new(&mut config.win_length, 2..=max_win_length).text("Marks in a row to win"));
+    config.win_length = config.win_length.min(max_win_length);
+    egui::ComboBox::from_label("Finished boards")
+        .selected_text(rules.finished_board.name())
+        .show_ui(ui, |ui| {
+            for rule in FinishedBoardRule::ALL {
+                ui.selectable_value(&mut rules.finished_board, rule, rule.name());
+            }
+        });
+    ui.checkbox(&mut rules.majority_tiebreak, "More won boards wins when there is no line");
+    ui.checkbox(&mut rules.misere, "Misère: making a line loses");
+    ui.add(egui::Slider::new(&mut rules.hints_per_game, 0..=10).text("Hints per player"));
+}
+
+/// Side human plays and how long computer thinks
+fn bot_settings_ui(ui: &mut egui::Ui, settings: &mut BotSettings, human: &mut Player) {
+    ui.horizontal(|ui| {
+        ui.label("Play as");
+        ui.radio_value(human, Player::X, "X");
+        ui.radio_value(human, Player::O, "O");
+    });
+    ui.horizontal(|ui| {
+        ui.label("Computer");
+        ui.radio_value(&mut settings.kind, BotKind::Mcts, "Tree search");
+        ui.radio_value(&mut settings.kind, BotKind::AlphaBeta, "Alpha-beta");
+    });
+    if settings.kind == BotKind::AlphaBeta {
+        ui.horizontal(|ui| {
+            ui.label("Difficulty");
+            for difficulty in Difficulty::ALL {
+                ui.radio_value(&mut settings.difficulty, difficulty, difficulty.name());
+            }
+        });
+        return;
+    }
+    ui.horizontal(|ui| {
+        ui.label("Computer thinks for");
+        if ui.radio(matches!(settings.budget, Budget::Iterations(_)), "iterations").clicked() {
+            settings.budget = Budget::Iterations(2000);
+        }
+        if ui.radio(matches!(settings.budget, Budget::Time(_)), "time").clicked() {
+            settings.budget = Budget::Time(Duration::from_secs(1));
+        }
+    });
+    match &mut settings.budget {
+        Budget::Iterations(iterations) => {
+            ui.add(egui::Slider::new(iterations, 100..=50000).logarithmic(true).text("Iterations per move"));
+        },
+        Budget::Time(time) => {
+            let mut seconds = time.as_secs_f32();
+            ui.add(egui::Slider::new(&mut seconds, 0.1..=10.).text("Seconds per move"));
+            *time = Duration::from_secs_f32(seconds);
+        },
+    }
+}
+
+use crate::network::{StartClient, DEFAULT_PORT};
 
 fn start_system(
     mut commands:Commands,
@@ -126,6 +291,18 @@ fn start_system(
             // TODO: idk do something
             info!("starting in server mode");
         },
+        StartClient::Local => {
+            next_game_state.set(GameState::StartingGame);
+            info!("starting local game");
+        },
+        StartClient::Computer(_) => {
+            next_game_state.set(GameState::StartingGame);
+            info!("starting game against computer");
+        },
+        StartClient::Replay => {
+            next_game_state.set(GameState::StartingGame);
+            info!("starting replay");
+        },
         _ => return
     }
 }
\ No newline at end of file
@@ -1,13 +1,16 @@
-use std::{collections::VecDeque, net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket}, time::SystemTime};
+use std::{collections::VecDeque, net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket}, time::SystemTime};
 
-use crate::{grid_cell::*, network::{GameEvent,StartClient},GameState};
+use crate::{grid_cell::*, network::{offline, GameEvent, Role, StartClient}, rules::{Game, GridConfig, Outcome, Player, Rules},GameState};
 // use crate::player::
 use bevy::{ecs::entity, prelude::*, utils::info, window::PrimaryWindow};
 use bevy_quinnet::{client::{certificate::CertificateVerificationMode, connection::ClientEndpointConfiguration, QuinnetClient, QuinnetClientPlugin}, shared::channels::ChannelsConfiguration};
 use bevy_egui::{egui::{self, Align2, Color32}, EguiContexts, EguiPlugin};
+use crate::toast::Toasts;
+use crate::{menu::SavedGames, notation::GameRecord, saves};
+use crate::hint::{hint_ui, Hint, HintCount};
 
 
-use super::{ReceiveEventQueue, SendEventQueue};
+use super::{server::{HostKey, ServerAddress}, ReceiveEventQueue, SendEventQueue};
 
 pub struct ClientPlugin;
 impl ClientPlugin {
@@ -19,12 +22,28 @@ impl Plugin for ClientPlugin {
         .insert_resource(ThisPlayer(CellState::Empty))
         .insert_resource(ReceiveEventQueue(VecDeque::new()))
         .insert_resource(SendEventQueue(VecDeque::new()))
-        .insert_resource(AvailableGrid(None))
+        .insert_resource(AvailableGrid(Vec::new()))
         .insert_resource(Winner(None))
+        .init_resource::<BoardTally>()
+        .insert_resource(CurrentGame::default())
+        .insert_resource(ShownGame::default())
+        .insert_resource(Preview(None))
+        .insert_resource(LoadedGame(None))
         .insert_state(CurrentPlayer::O)
         .add_systems(Update, start_connection.run_if(in_state(GameState::Connecting)))
-        .add_systems(Update, (handle_mouse_clicks,send_messages_to_server).chain().run_if(in_state(GameState::InGame)))
-        .add_systems(Update, (receive_server_messages,(occupy_cell,prevent_available_grid_lock).chain()).run_if(in_state(GameState::InGame)))
+        .add_systems(OnEnter(GameState::StartingGame), new_game)
+        .add_systems(Update, (
+            handle_mouse_clicks,
+            send_messages_to_server.run_if(not(offline)),
+            send_messages_locally.run_if(offline),
+        ).chain().run_if(in_state(GameState::InGame)))
+        .add_systems(Update, (
+            receive_server_messages.run_if(not(offline)),
+            occupy_cell,
+            update_shown_game,
+            mirror_turn,
+        ).chain().run_if(in_state(GameState::InGame)))
+        .add_systems(Update, (update_shown_game, mirror_turn).chain().run_if(in_state(GameState::Replaying)))
         .add_systems(Update, game_ui_system.run_if(in_state(GameState::InGame)))
         .add_systems(Update, clear_game.run_if(in_state(GameState::FinishingGame)));
     }
@@ -33,22 +52,26 @@ impl Plugin for ClientPlugin {
 struct ThisPlayer(CellState);
 
 /// Handles mouse click input updating events to send queue by adding cell that was clicked
+///
+/// Clicks that break the rules are reported to the player instead of being sent
 fn handle_mouse_clicks(
     mouse_input: Res<ButtonInput<MouseButton>>,
     camera_q: Query<(&Camera, &GlobalTransform), With<Camera>>,
     window_query: Query<&Window, With<PrimaryWindow>>,
     cell_q: Query<(Entity, &Cell, &GlobalTransform), Without<Grid>>,
     mut send_event_queue: ResMut<SendEventQueue>,
-    current_player: Res<State<CurrentPlayer>>,
     this_player:Res<ThisPlayer>,
-    winner: Res<Winner>,
+    start_client: Res<State<StartClient>>,
+    game: Res<CurrentGame>,
+    preview: Res<Preview>,
+    mut toasts: ResMut<Toasts>,
 ) {
-    if let Some(_) = winner.0 {
+    if preview.0.is_some() {
         return;
     }
-    if current_player.to_state() != this_player.0 {
+    let Some(player) = this_side(start_client.get(), *this_player, &game.0) else {
         return;
-    }
+    };
     let win = window_query.get_single().unwrap();
     let (camera, camera_transform) = camera_q.single();
     if mouse_input.just_pressed(MouseButton::Left) {
@@ -56,12 +79,17 @@ fn handle_mouse_clicks(
             .cursor_position()
             .and_then(|cursor| camera.viewport_to_world_2d(camera_transform, cursor))
         {
-            for (entity,  cell, transform) in &cell_q {
-                if (transform.translation().x - world_position.x).abs() < 45.
-                    && (transform.translation().y - world_position.y).abs() < 45.
-                    && cell.state == CellState::Empty
+            for (_entity,  cell, transform) in &cell_q {
+                if (transform.translation().x - world_position.x).abs() < CELL_PIXELS * 0.45
+                    && (transform.translation().y - world_position.y).abs() < CELL_PIXELS * 0.45
                 {
-                    send_event_queue.0.push_back(GameEvent::ClickedCell(cell.clone()));
+                    let Some(mv) = cell.to_move(&game.0.config()) else {
+                        break;
+                    };
+                    match game.0.validate_as(player, mv) {
+                        Ok(()) => send_event_queue.0.push_back(GameEvent::ClickedCell(cell.clone())),
+                        Err(error) => toasts.push(format!("Can't move there: {}", error)),
+                    }
                     break;
                 }
             }
@@ -69,92 +97,137 @@ fn handle_mouse_clicks(
     }
 }
 
+/// Player whose moves this window makes, both players take turns in local games
+fn this_side(start_client: &StartClient, this_player: ThisPlayer, game: &Game) -> Option<Player> {
+    match start_client {
+        StartClient::Local => Some(game.to_move()),
+        StartClient::Computer(human) => Some(*human),
+        _ => this_player.0.player(),
+    }
+}
+
 #[derive(States, Debug, Hash, Eq, PartialEq, Clone)]
 enum CurrentPlayer {
     X,
     O,
 }
-impl CurrentPlayer {
-    fn get_next(&self) -> CurrentPlayer {
-        match self {
-            CurrentPlayer::X => CurrentPlayer::O,
-            CurrentPlayer::O => CurrentPlayer::X,
-        }
-    }
-    fn to_state(&self) -> CellState {
-        match self {
-            CurrentPlayer::X => CellState::X,
-            CurrentPlayer::O => CellState::O,
+impl From<Player> for CurrentPlayer {
+    fn from(player: Player) -> Self {
+        match player {
+            Player::X => CurrentPlayer::X,
+            Player::O => CurrentPlayer::O,
         }
     }
 }
 
+/// Result of the game, None while it is still going
 #[derive(Resource,Clone, Copy)]
-pub struct Winner(pub Option<CellState>);
+pub struct Winner(pub Option<Outcome>);
 
+/// Amount of local grids won by each player
+#[derive(Resource,Clone, Copy, Default)]
+pub struct BoardTally {
+    pub x: usize,
+    pub o: usize,
+}
+
+/// Path of the grid where next move must be made, empty if any grid can be used
 #[derive(Resource)]
-pub struct AvailableGrid(pub Option<IVec2>);
+pub struct AvailableGrid(pub Vec<IVec2>);
 
-/// Prevents situations when player doesn't have valid cells to click because grid is filled
-fn prevent_available_grid_lock (
-    grid_cell_q:Query<(&Children,&Cell),(With<Grid>,With<Cell>)>,
-    cell_q:Query<(Entity,&Cell)>,
-    mut available_grid:ResMut<AvailableGrid>,
+/// Game model every cell entity mirrors
+#[derive(Resource,Default)]
+pub struct CurrentGame(pub Game);
 
-){
-    match available_grid.0 {
-        Some(available_grid_pos) => {
-            for (children,grid_cell) in &grid_cell_q{
-                if grid_cell.pos == available_grid_pos {
-                    let mut unfilled_count = 0;
-                    for (entity,cell) in &cell_q {
-                        if children.contains(&entity) && cell.state == CellState::Empty{
-                            unfilled_count+=1;
-                        }
-                    }
-                    if unfilled_count == 0{
-                        available_grid.0 = None;
-                    }
-                    return;
-                }
-            }
-        },
-        None => return,
-    }
+/// Position drawn on the screen, either current game or earlier position being previewed
+#[derive(Resource,Default)]
+pub struct ShownGame(pub Game);
+
+/// Amount of moves of previewed position, None when current position is shown
+///
+/// Grid is read-only while previewing
+#[derive(Resource)]
+pub struct Preview(pub Option<usize>);
+
+/// Game loaded from disk that next local game continues
+#[derive(Resource)]
+pub struct LoadedGame(pub Option<Game>);
+
+/// Creates game model with sizes and rules received from server, or continues loaded game
+fn new_game(
+    mut game: ResMut<CurrentGame>,
+    mut loaded_game: ResMut<LoadedGame>,
+    config: Res<GridConfig>,
+    rules: Res<Rules>,
+) {
+    game.0 = match loaded_game.0.take() {
+        Some(loaded) => loaded,
+        None => Game::new(*config, *rules),
+    };
 }
 
-/// Occupies cells if they are in received events queue
+/// Applies moves from received events queue to the game model
 /// 
 /// It is actually an event handler but there no events other then ClickedCell 
-fn occupy_cell (
-    mut cell_q: Query<(&mut Cell, &mut UpdateState), Without<Grid>>,
-    mut available_grid:ResMut<AvailableGrid>,
-    curr_player: Res<State<CurrentPlayer>>,
-    mut next_player: ResMut<NextState<CurrentPlayer>>,
+pub fn occupy_cell (
+    mut game: ResMut<CurrentGame>,
+    mut hint_count: ResMut<HintCount>,
+    mut hint: ResMut<Hint>,
     mut received_event_queue: ResMut<ReceiveEventQueue>,
+    mut toasts: ResMut<Toasts>,
 ) {
-
-    for event in &received_event_queue.0 {
+    for event in received_event_queue.0.drain(..) {
         match event {
             GameEvent::ClickedCell(clicked_cell) => {
-                for ( mut cell,mut update) in &mut cell_q{
-                    match available_grid.0 {
-                        Some(required_grid) if required_grid != cell.grid_pos.unwrap() => continue, //? UNWRAP 
-                        _ => {
-                            if cell.pos == clicked_cell.pos && cell.grid_pos == clicked_cell.grid_pos && cell.state == CellState::Empty {
-                                cell.state = curr_player.to_state();
-                                available_grid.0 = Some(cell.pos);
-                                *update = UpdateState(true);
-                                next_player.set(curr_player.get_next());
-                                return;
-                            }
-                        },
-                    }
+                let Some(mv) = clicked_cell.to_move(&game.0.config()) else {
+                    continue;
+                };
+                if let Err(error) = game.0.apply(mv) {
+                    toasts.push(format!("Move was rejected: {}", error));
                 }
             },
+            GameEvent::HintUsed(player) => {
+                hint_count.add(player);
+                hint.accepted(&game.0);
+            },
+            GameEvent::Rejected(message) => {
+                hint.refused();
+                toasts.push(format!("Server refused: {}", message));
+            },
+            GameEvent::Settings { .. } | GameEvent::Join { .. } => (),
         }
     }
-    received_event_queue.0.clear();
+}
+
+/// Rebuilds shown position when game or previewed move changes
+fn update_shown_game(
+    game: Res<CurrentGame>,
+    preview: Res<Preview>,
+    mut shown_game: ResMut<ShownGame>,
+) {
+    if !game.is_changed() && !preview.is_changed() {
+        return;
+    }
+    let history = game.0.history();
+    shown_game.0 = match preview.0 {
+        Some(moves) if moves < history.len() => game.0.replayed(moves),
+        _ => game.0.clone(),
+    };
+}
+
+/// Copies side to move and grid where next move must be made from game model
+fn mirror_turn(
+    game: Res<CurrentGame>,
+    shown_game: Res<ShownGame>,
+    mut available_grid:ResMut<AvailableGrid>,
+    mut next_player: ResMut<NextState<CurrentPlayer>>,
+) {
+    if shown_game.is_changed() {
+        available_grid.0 = indices_to_path(shown_game.0.forced_board(), &shown_game.0.config());
+    }
+    if game.is_changed() {
+        next_player.set(game.0.to_move().into());
+    }
 }
 
 
@@ -167,29 +240,56 @@ fn start_connection(
     client_mode_info: Res<State<StartClient>>,
     mut next_game_state: ResMut<NextState<GameState>>,
     mut this_player: ResMut<ThisPlayer>,
+    mut grid_config: ResMut<GridConfig>,
+    mut rules: ResMut<Rules>,
+    server_address: Res<ServerAddress>,
+    host_key: Option<Res<HostKey>>,
+    mut joined: Local<bool>,
 ) {
     if client.is_connected() 
     {
-        info!("successfully created connection to server");
-        next_game_state.set(GameState::StartingGame);
+        if !*joined {
+            // only the window hosting the server knows its key, so only its own client plays X for sure
+            let host_key = match client_mode_info.get() {
+                StartClient::Server => host_key.map(|key| key.0),
+                _ => None,
+            };
+            client.connection().try_send_message(GameEvent::Join { host_key });
+            *joined = true;
+        }
+        // game can only start once server tells which sizes, rules and side are used
+        while let Ok(Some((_, message))) = client.connection_mut().receive_message::<GameEvent>() {
+            if let GameEvent::Settings { config, rules: server_rules, role } = message {
+                info!("successfully created connection to server, joined as {:?}", role);
+                *grid_config = config;
+                *rules = server_rules;
+                *this_player = match role {
+                    Role::Player(player) => ThisPlayer(player.into()),
+                    Role::Spectator => ThisPlayer(CellState::Empty),
+                };
+                next_game_state.set(GameState::StartingGame);
+                // moves made before joining come after settings and are applied once game starts
+                break;
+            }
+            warn!("ignoring server message received before settings");
+        }
     } else if client.connections().count() == 0 {
         info!("attempting to create connection to server");
-        // setting this player cell type according to client type
-        *this_player = match client_mode_info.get() {
-            StartClient::Server => ThisPlayer(CellState::X),
-            StartClient::Client(_) => ThisPlayer(CellState::O),
-            _ => panic!("UNEXPECTED BEHAVIOR AAAAAAAAAAAAA")
-        };
+        *joined = false;
 
+        let server_addr = match client_mode_info.get() {
+            StartClient::Client(addr) => *addr,
+            StartClient::Server => SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), server_address.0.port()),
+            mode => {
+                warn!("{:?} games don't connect to a server", mode);
+                return;
+            },
+        };
         let _ = client
         .open_connection(
             ClientEndpointConfiguration::from_ips(
-                IpAddr::V4(match client_mode_info.get() {
-                    StartClient::Client(addr) => addr.clone(),
-                    StartClient::Server => Ipv4Addr::new(127, 0, 0, 1),
-                    _ => todo!()
-                }),
-                6000,
+                IpAddr::V4(*server_addr.ip()),
+                server_addr.port(),
                 IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)),
                 0,
             ),
@@ -210,6 +310,14 @@ fn send_messages_to_server(mut client: ResMut<QuinnetClient>,mut messages:ResMut
         }
     }
 }
+/// Games without network have no server, so sent messages are received right away
+fn send_messages_locally(
+    mut messages: ResMut<SendEventQueue>,
+    mut received_event_queue: ResMut<ReceiveEventQueue>,
+) {
+    received_event_queue.0.extend(messages.0.drain(..));
+}
+
 /// Receives messages from server and puts them in receive queue
 fn receive_server_messages(
     mut client: ResMut<QuinnetClient>,
@@ -222,10 +330,36 @@ fn receive_server_messages(
 
 
 
+/// Describes who won and why
+fn outcome_text(outcome: Outcome) -> String {
+    let name = |player: Player| match player {
+        Player::X => "X",
+        Player::O => "O",
+    };
+    match outcome {
+        Outcome::Line(player) => format!("WINNER:{}, made a line", name(player)),
+        Outcome::MisereLine(player) => format!("WINNER:{}, {} made a line and lost", name(player.other()), name(player)),
+        Outcome::Boards(player) => format!("WINNER:{} by won grids count", name(player)),
+        Outcome::Draw => "Draw".to_string(),
+    }
+}
+
 fn game_ui_system(
     mut contexts: EguiContexts,
     winner: Res<Winner>,
+    tally: Res<BoardTally>,
     current_player: Res<State<CurrentPlayer>>,
+    start_client: Res<State<StartClient>>,
+    mut game: ResMut<CurrentGame>,
+    shown_game: Res<ShownGame>,
+    mut preview: ResMut<Preview>,
+    mut save_name: Local<String>,
+    mut toasts: ResMut<Toasts>,
+    this_player: Res<ThisPlayer>,
+    hint_count: Res<HintCount>,
+    mut hint: ResMut<Hint>,
+    mut send_event_queue: ResMut<SendEventQueue>,
+    mut saved_games: ResMut<SavedGames>,
     mut next_game_state: ResMut<NextState<GameState>>
 ) {
     egui::Window::new("Game info").anchor(Align2::LEFT_TOP, [0.,0.]).show(contexts.ctx_mut(), |ui| {
@@ -233,17 +367,58 @@ fn game_ui_system(
             CurrentPlayer::X => "X",
             CurrentPlayer::O => "O",
         }));
+        if !start_client.get().is_offline() {
+            ui.label(match this_player.0.player() {
+                Some(player) => format!("You play {}", player),
+                None => "You are watching".to_string(),
+            });
+        }
+        ui.label(format!("Won grids: X {} - O {}", tally.x, tally.o));
+        if start_client.get() == &StartClient::Local {
+            ui.horizontal(|ui| {
+                if ui.add_enabled(game.0.can_undo(), egui::Button::new("Undo")).clicked() {
+                    game.0.undo();
+                }
+                if ui.add_enabled(game.0.can_redo(), egui::Button::new("Redo")).clicked() {
+                    game.0.redo();
+                }
+            });
+        }
+        let player = this_side(start_client.get(), *this_player, &game.0);
+        hint_ui(ui, player, &game.0, &hint_count, &mut hint, &mut send_event_queue);
+        move_list_ui(ui, &game.0, &mut preview);
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut *save_name);
+            if ui.button("Save game").clicked() {
+                let record = GameRecord::from_game(&game.0, "Player X", "Player O", &saves::today());
+                match saves::save_game(&save_name, &record) {
+                    Ok(path) => {
+                        toasts.push(format!("Saved to {}", path.display()));
+                        saved_games.0 = saves::saved_games();
+                    },
+                    Err(error) => toasts.push(format!("Can't save game: {}", error)),
+                }
+            }
+        });
+        // previewed move exports the position on the board, not the live one
+        if ui.button("Copy position").clicked() {
+            let position = shown_game.0.position();
+            info!("position: {}", position);
+            ui.output_mut(|output| output.copied_text = position);
+            toasts.push("Position copied to clipboard");
+        }
         match winner.0 {
-            Some(player) => {
-                ui.label(format!("WINNER:{}",match player {
-                    CellState::X => "X",
-                    CellState::O => "O",
-                    _ => "IDK you broke the game"
-                }));
+            Some(outcome) => {
+                ui.label(outcome_text(outcome));
                 if ui.button("Press me to go to menu").clicked() {
                     next_game_state.set(GameState::FinishingGame);
                 }
             },
+            // games without network can be left at any time, computer stops thinking
+            None if start_client.get().is_offline() && ui.button("Leave game").clicked() => {
+                next_game_state.set(GameState::FinishingGame);
+            },
             None => (),
         }
     });
@@ -252,6 +427,33 @@ fn game_ui_system(
 
 
 
+/// Numbered list of moves made, clicking a move previews position after it
+fn move_list_ui(ui: &mut egui::Ui, game: &Game, preview: &mut Preview) {
+    ui.separator();
+    ui.label("Moves");
+    let config = game.config();
+    egui::ScrollArea::vertical().max_height(200.).show(ui, |ui| {
+        let mut player = game.start_player();
+        for (index, mv) in game.history().iter().enumerate() {
+            let moves = index + 1;
+            let text = format!("{}: {} {}", moves, player, mv.notation(&config));
+            if ui.selectable_label(preview.0 == Some(moves), text).clicked() {
+                preview.0 = match preview.0 == Some(moves) {
+                    true => None,
+                    false => Some(moves),
+                };
+            }
+            player = player.other();
+        }
+    });
+    if preview.0.is_some() {
+        ui.colored_label(Color32::YELLOW, "Previewing, board is read-only");
+        if ui.button("Back to game").clicked() {
+            preview.0 = None;
+        }
+    }
+}
+
 fn clear_game(
     mut client: ResMut<QuinnetClient>,
     sprites: Query<Entity,With<Sprite>>,
@@ -259,6 +461,9 @@ fn clear_game(
     mut this_player: ResMut<ThisPlayer>,
     mut available_grid: ResMut<AvailableGrid>,
     mut winner: ResMut<Winner>,
+    mut tally: ResMut<BoardTally>,
+    mut game: ResMut<CurrentGame>,
+    mut preview: ResMut<Preview>,
     mut next_player: ResMut<NextState<CurrentPlayer>>,
 ){
     client.close_all_connections();
@@ -266,8 +471,11 @@ fn clear_game(
         commands.entity(entity).despawn();
     }
     *this_player = ThisPlayer(CellState::Empty);
-    *available_grid = AvailableGrid(None);
+    *available_grid = AvailableGrid(Vec::new());
     *winner = Winner(None);
+    *tally = BoardTally::default();
+    *game = CurrentGame::default();
+    *preview = Preview(None);
     next_player.set(CurrentPlayer::O);
 }
 
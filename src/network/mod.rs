@@ -1,12 +1,15 @@
-use std::{collections::VecDeque, net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket}, time::SystemTime};
+use std::{collections::VecDeque, net::SocketAddrV4};
 
-use crate::{grid_cell::*};
+use crate::{grid_cell::*, rules::{GridConfig, Player, Rules}};
 // use crate::player::
 use bevy::{ecs::entity, prelude::*, utils::info, window::PrimaryWindow};
 use serde::{Deserialize, Serialize};
 pub mod server;
 pub mod client;
 
+/// Port servers listen on unless told otherwise
+pub const DEFAULT_PORT: u16 = 6000;
+
 /// Queue of events to be send to client from server or from server to client
 #[derive(Resource)]
 pub struct SendEventQueue(pub VecDeque<GameEvent>);
@@ -19,6 +22,30 @@ pub struct ReceiveEventQueue(pub VecDeque<GameEvent>);
 #[derive(Serialize,Deserialize)]
 pub enum GameEvent {
     ClickedCell(Cell),
+    /// Player asked for a hint, everyone counts hints of both players
+    HintUsed(Player),
+    /// Sizes and rules chosen by server and role it gave, sent to every client that connects
+    Settings{
+        config: GridConfig,
+        rules: Rules,
+        role: Role,
+    },
+    /// Server refused an event, only the client that sent it gets the reason
+    Rejected(String),
+    /// First event of every client, server answers with `Settings`
+    ///
+    /// Only the window hosting the server knows its host key, its own client gets X with it
+    Join{
+        host_key: Option<u64>,
+    },
+}
+
+/// Side server gave to a client, clients can't choose it
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    Player(Player),
+    /// Both sides were taken when client joined, it can only watch
+    Spectator,
 }
 
 #[derive(States, Debug, Clone, PartialEq, Eq, Hash)]
@@ -28,7 +55,25 @@ pub enum StartClient{
     /// Attempted to start but couldn't use given ip address
     IncorrectAddress,
     /// Start as client and connect to given address
-    Client(Ipv4Addr),
+    Client(SocketAddrV4),
     /// Start as server
-    Server
+    Server,
+    /// Start game without network, both players use this window
+    Local,
+    /// Watch recorded game, no moves can be made
+    Replay,
+    /// Play against the computer, human plays given player
+    Computer(Player)
+}
+
+impl StartClient {
+    /// True for games that don't use network
+    pub fn is_offline(&self) -> bool {
+        matches!(self, StartClient::Local | StartClient::Replay | StartClient::Computer(_))
+    }
+}
+
+/// Run condition for systems of games without network
+pub fn offline(start_client: Res<State<StartClient>>) -> bool {
+    start_client.get().is_offline()
 }
\ No newline at end of file
@@ -1,9 +1,9 @@
-use std::{collections::VecDeque, net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket}, time::SystemTime};
+use std::{collections::{HashMap, VecDeque}, net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket}, time::SystemTime};
 
-use crate::{grid_cell::*, network::{GameEvent,StartClient},GameState};
+use crate::{grid_cell::*, hint::HintCount, network::{GameEvent,Role,StartClient,DEFAULT_PORT}, notation::variant_text, rules::{Game, GridConfig, Player, Rules},GameState};
 // use crate::player::
-use bevy::{ecs::entity, prelude::*, utils::info, window::PrimaryWindow};
-use bevy_quinnet::{server::{certificate::CertificateRetrievalMode, server_listening, QuinnetServer, QuinnetServerPlugin, ServerEndpointConfiguration}, shared::channels::ChannelsConfiguration};
+use bevy::{app::AppExit, ecs::entity, prelude::*, utils::info, window::PrimaryWindow};
+use bevy_quinnet::{server::{certificate::CertificateRetrievalMode, server_listening, ConnectionLostEvent, Endpoint, QuinnetServer, QuinnetServerPlugin, ServerEndpointConfiguration}, shared::{channels::ChannelsConfiguration, ClientId}};
 use serde::{Deserialize, Serialize};
 
 pub struct ServerPlugin;
@@ -11,30 +11,189 @@ pub struct ServerPlugin;
 impl Plugin for ServerPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(QuinnetServerPlugin::default())
+        .init_resource::<ServerAddress>()
+        .insert_resource(HostKey::generate())
         .add_systems(
             Update, 
             start_listening.run_if(in_state(GameState::CreatingServer).and_then(in_state(StartClient::Server))))
         .add_systems(
             Update, 
-            handle_client_messages.run_if(in_state(GameState::InGame).and_then(in_state(StartClient::Server))))
+            (handle_client_messages, forget_lost_clients).run_if(in_state(StartClient::Server)))
         .add_systems(Update, stop_server.run_if(in_state(GameState::FinishingGame)));
     }
 }
 
+/// Server without window that only hosts games, nobody plays on it
+///
+/// Address, sizes and rules are taken from `ServerAddress`, `GridConfig` and `Rules` resources
+pub struct DedicatedServerPlugin;
+
+impl Plugin for DedicatedServerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(QuinnetServerPlugin::default())
+        .init_resource::<ServerAddress>()
+        .init_resource::<GridConfig>()
+        .init_resource::<Rules>()
+        .add_systems(Startup, start_dedicated_server)
+        .add_systems(Update, (handle_client_messages, (forget_lost_clients, reset_empty_server).chain()));
+    }
+}
+
+/// Address and port server listens on
+#[derive(Resource, Clone, Copy)]
+pub struct ServerAddress(pub SocketAddr);
+
+impl Default for ServerAddress {
+    fn default() -> Self {
+        ServerAddress(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), DEFAULT_PORT))
+    }
+}
+
+/// Secret of the window hosting the server, its own client joins with it to play X
+#[derive(Resource, Clone, Copy)]
+pub struct HostKey(pub u64);
+
+impl HostKey {
+    /// Key from the random generator of the OS, so other clients can't guess it
+    fn generate() -> HostKey {
+        let mut bytes = [0; 8];
+        getrandom::getrandom(&mut bytes).expect("OS has a random generator");
+        HostKey(u64::from_le_bytes(bytes))
+    }
+}
+
+/// Server copy of the game, clients only get events it accepted
+#[derive(Resource)]
+pub struct ServerGame {
+    pub game: Game,
+    pub hints: HintCount,
+    /// Role of every connected client
+    pub roles: HashMap<ClientId, Role>,
+    /// Key of the hosting window, None on dedicated server
+    pub host_key: Option<u64>,
+}
+
+impl ServerGame {
+    pub fn new(config: GridConfig, rules: Rules, host_key: Option<u64>) -> ServerGame {
+        ServerGame {
+            game: Game::new(config, rules),
+            hints: HintCount::default(),
+            roles: HashMap::new(),
+            host_key,
+        }
+    }
+
+    /// Gives client a side, clients joining after both sides are taken watch
+    ///
+    /// Host's own client plays X and other clients get O. Without a host X goes to whoever joins first.
+    /// Clients that already joined keep their role
+    pub fn join(&mut self, client_id: ClientId, host_key: Option<u64>) -> Role {
+        if let Some(role) = self.roles.get(&client_id) {
+            return *role;
+        }
+        let sides = match self.host_key {
+            Some(key) if host_key == Some(key) => vec![Player::X],
+            Some(_) => vec![Player::O],
+            None => vec![Player::X, Player::O],
+        };
+        let role = sides
+            .into_iter()
+            .map(Role::Player)
+            .find(|role| !self.roles.values().any(|taken| taken == role))
+            .unwrap_or(Role::Spectator);
+        self.roles.insert(client_id, role);
+        role
+    }
+
+    /// Frees side of the client, so a player that lost connection can join again
+    pub fn leave(&mut self, client_id: ClientId) {
+        self.roles.remove(&client_id);
+    }
+
+    /// Side the client plays, spectators and unknown clients can't make moves
+    fn player_of(&self, client_id: ClientId) -> Result<Player, String> {
+        match self.roles.get(&client_id) {
+            Some(Role::Player(player)) => Ok(*player),
+            _ => Err("spectators can't play".to_string()),
+        }
+    }
+
+    /// Moves and hints so far, they bring a client that joined late to the current position
+    pub fn catch_up_events(&self) -> Vec<GameEvent> {
+        let config = self.game.config();
+        let mut game = self.game.replayed(0);
+        let mut events = Vec::new();
+        for mv in self.game.history() {
+            events.push(GameEvent::ClickedCell(Cell {
+                path: indices_to_path(mv.path(), &config),
+                state: game.to_move().into(),
+            }));
+            game.apply(*mv).expect("moves of history are legal");
+        }
+        for player in [Player::X, Player::O] {
+            events.extend((0..self.hints.used(player)).map(|_| GameEvent::HintUsed(player)));
+        }
+        events
+    }
+
+    /// Checks event of a client against the rules, returns event to broadcast or reason to refuse it
+    ///
+    /// Moves and hints count for the side server gave to the client, whatever mark or player the event has
+    pub fn accept(&mut self, client_id: ClientId, event: GameEvent) -> Result<GameEvent, String> {
+        match event {
+            GameEvent::ClickedCell(cell) => {
+                let player = self.player_of(client_id)?;
+                let mv = cell.to_move(&self.game.config()).ok_or("there is no such cell")?;
+                self.game.apply_as(player, mv).map_err(|error| error.to_string())?;
+                Ok(GameEvent::ClickedCell(Cell {
+                    path: cell.path,
+                    state: player.into(),
+                }))
+            },
+            GameEvent::HintUsed(_) => {
+                let player = self.player_of(client_id)?;
+                if self.game.outcome().is_some() || self.game.to_move() != player {
+                    return Err("hints can only be asked on your turn".to_string());
+                }
+                if self.hints.used(player) >= self.game.rules().hints_per_game {
+                    return Err("no hints left".to_string());
+                }
+                self.hints.add(player);
+                Ok(GameEvent::HintUsed(player))
+            },
+            GameEvent::Settings { .. } => Err("only server chooses sizes and rules".to_string()),
+            GameEvent::Rejected(_) => Err("only server refuses events".to_string()),
+            GameEvent::Join { .. } => Err("clients join only once".to_string()),
+        }
+    }
+}
+
+/// Opens server endpoint with self signed certificate
+fn listen(server: &mut QuinnetServer, address: SocketAddr) -> Result<(), String> {
+    info!("creating server endpoint on {}", address);
+    server
+        .start_endpoint(
+            ServerEndpointConfiguration::from_ip(address.ip(), address.port()),
+            CertificateRetrievalMode::GenerateSelfSigned { server_hostname: "serv".to_string() },
+            ChannelsConfiguration::default(),
+        )
+        .map(|_| ())
+        .map_err(|error| format!("can't listen on {}: {:?}", address, error))
+}
+
 /// Starts listening for connection
 fn start_listening(
+    mut commands: Commands,
     mut server: ResMut<QuinnetServer>,
     mut next_game_state: ResMut<NextState<GameState>>,
+    address: Res<ServerAddress>,
+    config: Res<GridConfig>,
+    rules: Res<Rules>,
+    host_key: Res<HostKey>,
 ) {
     if !server.is_listening() {
-        info!("creating server endpoint");
-        server
-            .start_endpoint(
-                ServerEndpointConfiguration::from_ip(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 6000),
-                CertificateRetrievalMode::GenerateSelfSigned { server_hostname: "serv".to_string() },
-                ChannelsConfiguration::default(),
-            )
-            .unwrap();
+        listen(&mut server, address.0).unwrap();
+        commands.insert_resource(ServerGame::new(*config, *rules, Some(host_key.0)));
     }
     info!("checking for connections:");
     if server.endpoint().clients().len() > 0 {
@@ -45,20 +204,233 @@ fn start_listening(
     }
 }
 
-/// Here server just broadcasts messages it gets
+/// Gives client a role and tells it sizes, rules and moves made before it joined
+fn welcome(endpoint: &mut Endpoint, server_game: &mut ServerGame, client_id: ClientId, host_key: Option<u64>) {
+    let role = server_game.join(client_id, host_key);
+    info!("client {} joined as {:?}", client_id, role);
+    endpoint.try_send_message(client_id, GameEvent::Settings {
+        config: server_game.game.config(),
+        rules: server_game.game.rules(),
+        role,
+    });
+    for event in server_game.catch_up_events() {
+        endpoint.try_send_message(client_id, event);
+    }
+}
+
+/// Frees roles of clients that left
+fn forget_lost_clients(
+    mut lost_connections: EventReader<ConnectionLostEvent>,
+    server_game: Option<ResMut<ServerGame>>,
+) {
+    let Some(mut server_game) = server_game else {
+        return;
+    };
+    for lost in lost_connections.read() {
+        info!("client {} left", lost.id);
+        server_game.leave(lost.id);
+    }
+}
+
+/// Welcomes joining clients and broadcasts events server game accepts, sender of any other event is told
+/// why it was refused
 fn handle_client_messages(
     mut server: ResMut<QuinnetServer>,
+    server_game: Option<ResMut<ServerGame>>,
 ) {
-    let mut endpoint = server.endpoint_mut();
+    let Some(mut server_game) = server_game else {
+        return;
+    };
+    let endpoint = server.endpoint_mut();
     for client_id in endpoint.clients() {
-        while let Some(message) = endpoint.try_receive_message_from::<GameEvent>(client_id) {
-            endpoint.broadcast_message(message.1);
+        while let Some((_, message)) = endpoint.try_receive_message_from::<GameEvent>(client_id) {
+            if let GameEvent::Join { host_key } = message {
+                welcome(endpoint, &mut server_game, client_id, host_key);
+                continue;
+            }
+            match server_game.accept(client_id, message) {
+                Ok(event) => endpoint.try_broadcast_message(event),
+                Err(reason) => {
+                    info!("refused event of client {}: {}", client_id, reason);
+                    endpoint.try_send_message(client_id, GameEvent::Rejected(reason));
+                },
+            }
         }
     }
 }
 
+/// Dedicated server starts a new game once everyone has left
+fn reset_empty_server(
+    mut server: ResMut<QuinnetServer>,
+    mut lost_connections: EventReader<ConnectionLostEvent>,
+    server_game: Option<ResMut<ServerGame>>,
+) {
+    let Some(mut server_game) = server_game else {
+        return;
+    };
+    if lost_connections.read().count() > 0 && server.endpoint_mut().clients().is_empty() {
+        info!("all clients left, starting new game");
+        let game = &server_game.game;
+        *server_game = ServerGame::new(game.config(), game.rules(), None);
+    }
+}
+
+/// Dedicated server can't do anything without endpoint, so it quits when address can't be used
+fn start_dedicated_server(
+    mut commands: Commands,
+    mut server: ResMut<QuinnetServer>,
+    address: Res<ServerAddress>,
+    config: Res<GridConfig>,
+    rules: Res<Rules>,
+    mut exit: EventWriter<AppExit>,
+) {
+    match listen(&mut server, address.0) {
+        Ok(()) => {
+            info!("hosting games of {}", variant_text(&config, &rules));
+            commands.insert_resource(ServerGame::new(*config, *rules, None));
+        },
+        Err(error) => {
+            error!("{}", error);
+            exit.send(AppExit);
+        },
+    }
+}
+
 fn stop_server(
     mut server: ResMut<QuinnetServer>,
 ){
     server.stop_endpoint();
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::{BoardState, MoveError};
+
+    const X_CLIENT: ClientId = 1;
+    const O_CLIENT: ClientId = 2;
+    const WATCHER: ClientId = 3;
+
+    /// Dedicated server game that X, O and a spectator joined, in that order
+    fn joined_game(rules: Rules) -> ServerGame {
+        let mut server_game = ServerGame::new(GridConfig::default(), rules, None);
+        for client_id in [X_CLIENT, O_CLIENT, WATCHER] {
+            server_game.join(client_id, None);
+        }
+        server_game
+    }
+
+    /// Click on the cell of a move, `state` is the mark client claims to make
+    fn click(path: &[usize], state: CellState) -> GameEvent {
+        GameEvent::ClickedCell(Cell {
+            path: indices_to_path(path, &GridConfig::default()),
+            state,
+        })
+    }
+
+    /// Mark of the broadcast click, None if event was refused or isn't a click
+    fn broadcast_mark(result: Result<GameEvent, String>) -> Option<CellState> {
+        match result {
+            Ok(GameEvent::ClickedCell(cell)) => Some(cell.state),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn dedicated_server_gives_sides_in_join_order() {
+        let mut server_game = ServerGame::new(GridConfig::default(), Rules::default(), None);
+        assert_eq!(server_game.join(O_CLIENT, Some(7)), Role::Player(Player::X));
+        assert_eq!(server_game.join(X_CLIENT, None), Role::Player(Player::O));
+        assert_eq!(server_game.join(WATCHER, None), Role::Spectator);
+    }
+
+    #[test]
+    fn host_plays_x_whenever_it_joins() {
+        let mut server_game = ServerGame::new(GridConfig::default(), Rules::default(), Some(7));
+        assert_eq!(server_game.join(O_CLIENT, None), Role::Player(Player::O));
+        // wrong key is just another client
+        assert_eq!(server_game.join(WATCHER, Some(8)), Role::Spectator);
+        assert_eq!(server_game.join(X_CLIENT, Some(7)), Role::Player(Player::X));
+        // joining again doesn't change sides
+        assert_eq!(server_game.join(WATCHER, Some(7)), Role::Spectator);
+        assert_eq!(server_game.join(O_CLIENT, Some(7)), Role::Player(Player::O));
+    }
+
+    #[test]
+    fn players_that_reconnect_get_their_side_back() {
+        let mut server_game = ServerGame::new(GridConfig::default(), Rules::default(), Some(7));
+        server_game.join(X_CLIENT, Some(7));
+        server_game.join(O_CLIENT, None);
+        server_game.leave(O_CLIENT);
+        server_game.leave(X_CLIENT);
+        // connection is new, so is the id
+        assert_eq!(server_game.join(5, None), Role::Player(Player::O));
+        assert_eq!(server_game.join(6, Some(7)), Role::Player(Player::X));
+        assert_eq!(server_game.join(WATCHER, None), Role::Spectator);
+    }
+
+    #[test]
+    fn moves_out_of_turn_are_refused() {
+        let mut server_game = joined_game(Rules::default());
+        let result = server_game.accept(X_CLIENT, click(&[0, 4], CellState::X));
+        assert_eq!(result.err(), Some(MoveError::NotYourTurn.to_string()));
+        assert!(server_game.game.history().is_empty());
+    }
+
+    #[test]
+    fn spectators_cant_play() {
+        let mut server_game = joined_game(Rules::default());
+        assert!(server_game.accept(WATCHER, click(&[0, 4], CellState::O)).is_err());
+        assert!(server_game.accept(WATCHER, GameEvent::HintUsed(Player::O)).is_err());
+        assert!(server_game.game.history().is_empty());
+        assert_eq!(server_game.hints.used(Player::O), 0);
+    }
+
+    #[test]
+    fn illegal_moves_are_refused() {
+        let mut server_game = joined_game(Rules::default());
+        assert_eq!(broadcast_mark(server_game.accept(O_CLIENT, click(&[0, 4], CellState::O))), Some(CellState::O));
+        let before = server_game.game.clone();
+        // X was sent to board 4
+        let result = server_game.accept(X_CLIENT, click(&[0, 0], CellState::X));
+        assert_eq!(result.err(), Some(MoveError::WrongBoard.to_string()));
+        assert!(server_game.accept(X_CLIENT, click(&[4, 9], CellState::X)).is_err());
+        assert_eq!(server_game.game, before);
+        assert_eq!(broadcast_mark(server_game.accept(X_CLIENT, click(&[4, 4], CellState::X))), Some(CellState::X));
+        // O is sent back to board 4, where the center is taken
+        let result = server_game.accept(O_CLIENT, click(&[4, 4], CellState::O));
+        assert_eq!(result.err(), Some(MoveError::Occupied.to_string()));
+        assert_eq!(server_game.game.history().len(), 2);
+    }
+
+    #[test]
+    fn hints_beyond_budget_are_refused() {
+        let mut server_game = joined_game(Rules { hints_per_game: 1, ..Rules::default() });
+        assert!(server_game.accept(X_CLIENT, GameEvent::HintUsed(Player::X)).is_err());
+        assert!(server_game.accept(O_CLIENT, GameEvent::HintUsed(Player::O)).is_ok());
+        assert!(server_game.accept(O_CLIENT, GameEvent::HintUsed(Player::O)).is_err());
+        assert_eq!((server_game.hints.used(Player::X), server_game.hints.used(Player::O)), (0, 1));
+    }
+
+    #[test]
+    fn fabricated_events_change_nothing() {
+        let mut server_game = joined_game(Rules::default());
+        let before = server_game.game.clone();
+        // X claims to make the mark of O on O's turn
+        assert!(server_game.accept(X_CLIENT, click(&[0, 4], CellState::O)).is_err());
+        // X asks a hint in the name of O
+        assert!(server_game.accept(X_CLIENT, GameEvent::HintUsed(Player::O)).is_err());
+        let settings = GameEvent::Settings {
+            config: GridConfig { depth: 1, ..GridConfig::default() },
+            rules: Rules::default(),
+            role: Role::Player(Player::O),
+        };
+        assert!(server_game.accept(X_CLIENT, settings).is_err());
+        assert!(server_game.accept(X_CLIENT, GameEvent::Rejected("cheating".to_string())).is_err());
+        assert_eq!(server_game.game, before);
+        assert_eq!(server_game.roles[&X_CLIENT], Role::Player(Player::X));
+        // mark claimed by the client doesn't matter, the move is made for the side it plays
+        assert_eq!(broadcast_mark(server_game.accept(O_CLIENT, click(&[0, 4], CellState::X))), Some(CellState::O));
+        assert_eq!(server_game.game.board_state(&[0, 4]), BoardState::Won(Player::O));
+    }
+}
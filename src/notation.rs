@@ -0,0 +1,359 @@
+//! Text record of a game, header tags followed by numbered moves:
+//!
+//! ```text
+//! [Variant "board=3 outer=3 win=3 depth=2 finished=free-move tiebreak=no misere=no hints=3"]
+//! [X "Alice"]
+//! [O "Bob"]
+//! [Result "O"]
+//! [Date "2024.05.01"]
+//!
+//! 1. e/c1 2. c/b2 3. e/a3 O
+//! ```
+//!
+//! Result is `X` or `O` for the winner, `draw`, or `*` for a game that is not finished.
+//! Games that didn't start from the empty board have a `Position` tag written with `Game::position`.
+//! Moves are written with `Move::notation`, the player is implied because turns alternate.
+
+use std::{fmt, str::FromStr};
+
+use crate::rules::{FinishedBoardRule, Game, GridConfig, Move, MoveError, Player, Rules};
+
+/// Moves written on one line of the record
+const MOVES_PER_LINE: usize = 10;
+
+/// How the recorded game ended
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordResult {
+    Win(Player),
+    Draw,
+    Unfinished,
+}
+
+impl RecordResult {
+    /// Result of the game in its current position
+    pub fn of(game: &Game) -> RecordResult {
+        match game.outcome() {
+            Some(outcome) => match outcome.winner() {
+                Some(player) => RecordResult::Win(player),
+                None => RecordResult::Draw,
+            },
+            None => RecordResult::Unfinished,
+        }
+    }
+}
+
+impl fmt::Display for RecordResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordResult::Win(player) => write!(f, "{}", player),
+            RecordResult::Draw => write!(f, "draw"),
+            RecordResult::Unfinished => write!(f, "*"),
+        }
+    }
+}
+
+impl FromStr for RecordResult {
+    type Err = NotationError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "X" => Ok(RecordResult::Win(Player::X)),
+            "O" => Ok(RecordResult::Win(Player::O)),
+            "draw" => Ok(RecordResult::Draw),
+            "*" => Ok(RecordResult::Unfinished),
+            _ => Err(NotationError::BadResult(text.to_string())),
+        }
+    }
+}
+
+/// Reason a record can't be read
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NotationError {
+    /// Line starting with `[` is not a `[Name "value"]` tag
+    BadTag(String),
+    /// Variant tag is missing or has unknown keys or values
+    BadVariant(String),
+    /// Result tag or result at the end of moves is not `X`, `O`, `draw` or `*`
+    BadResult(String),
+    /// Position tag is not a position of this variant
+    BadPosition(String),
+    /// Text in move list is not a move of this variant
+    BadMove(String),
+    /// Move with this number breaks the rules
+    IllegalMove(usize, MoveError),
+}
+
+impl fmt::Display for NotationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NotationError::BadTag(line) => write!(f, "can't read tag {}", line),
+            NotationError::BadVariant(variant) => write!(f, "unknown variant \"{}\"", variant),
+            NotationError::BadResult(result) => write!(f, "unknown result \"{}\"", result),
+            NotationError::BadPosition(position) => write!(f, "can't read position \"{}\"", position),
+            NotationError::BadMove(text) => write!(f, "\"{}\" is not a move", text),
+            NotationError::IllegalMove(number, error) => write!(f, "move {} is illegal: {}", number, error),
+        }
+    }
+}
+
+impl std::error::Error for NotationError {}
+
+/// Game with the information needed to share or archive it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GameRecord {
+    pub config: GridConfig,
+    pub rules: Rules,
+    /// Name of the player with X
+    pub x_player: String,
+    /// Name of the player with O
+    pub o_player: String,
+    pub result: RecordResult,
+    /// Date the game was played, written as `YYYY.MM.DD`
+    pub date: String,
+    /// Position moves are made from, None for the empty board
+    pub position: Option<String>,
+    pub moves: Vec<Move>,
+}
+
+impl GameRecord {
+    /// Records moves made so far in the game
+    pub fn from_game(game: &Game, x_player: &str, o_player: &str, date: &str) -> GameRecord {
+        GameRecord {
+            config: game.config(),
+            rules: game.rules(),
+            x_player: x_player.to_string(),
+            o_player: o_player.to_string(),
+            result: RecordResult::of(game),
+            date: date.to_string(),
+            position: game.start_position(),
+            moves: game.history().to_vec(),
+        }
+    }
+    /// Replays recorded moves
+    pub fn to_game(&self) -> Result<Game, NotationError> {
+        let mut game = match &self.position {
+            Some(position) => Game::from_position(position, self.rules)
+                .filter(|game| game.config() == self.config)
+                .ok_or_else(|| NotationError::BadPosition(position.clone()))?,
+            None => Game::new(self.config, self.rules),
+        };
+        for (index, mv) in self.moves.iter().enumerate() {
+            game.apply(*mv).map_err(|error| NotationError::IllegalMove(index + 1, error))?;
+        }
+        Ok(game)
+    }
+}
+
+/// Variant tag value, every size and rule as `key=value`
+pub fn variant_text(config: &GridConfig, rules: &Rules) -> String {
+    let yes_no = |flag: bool| if flag { "yes" } else { "no" };
+    format!(
+        "board={} outer={} win={} depth={} finished={} tiebreak={} misere={} hints={}",
+        config.board_size,
+        config.outer_size,
+        config.win_length,
+        config.depth,
+        finished_board_text(rules.finished_board),
+        yes_no(rules.majority_tiebreak),
+        yes_no(rules.misere),
+        rules.hints_per_game,
+    )
+}
+
+fn finished_board_text(rule: FinishedBoardRule) -> &'static str {
+    match rule {
+        FinishedBoardRule::FreeMove => "free-move",
+        FinishedBoardRule::PlayInWonBoards => "play-in-won",
+        FinishedBoardRule::WonBoardsDrawOnly => "won-draw-only",
+    }
+}
+
+/// Reads variant tag value, keys that are left out keep default values
+pub fn parse_variant(text: &str) -> Result<(GridConfig, Rules), NotationError> {
+    let bad_variant = || NotationError::BadVariant(text.to_string());
+    let mut config = GridConfig::default();
+    let mut rules = Rules::default();
+    for pair in text.split_whitespace() {
+        let (key, value) = pair.split_once('=').ok_or_else(bad_variant)?;
+        let number = || value.parse::<usize>().map_err(|_| bad_variant());
+        let flag = || match value {
+            "yes" => Ok(true),
+            "no" => Ok(false),
+            _ => Err(bad_variant()),
+        };
+        match key {
+            "board" => config.board_size = number()?,
+            "outer" => config.outer_size = number()?,
+            "win" => config.win_length = number()?,
+            "depth" => config.depth = number()?,
+            "finished" => {
+                rules.finished_board = FinishedBoardRule::ALL
+                    .into_iter()
+                    .find(|rule| finished_board_text(*rule) == value)
+                    .ok_or_else(bad_variant)?
+            },
+            "tiebreak" => rules.majority_tiebreak = flag()?,
+            "misere" => rules.misere = flag()?,
+            "hints" => rules.hints_per_game = number()?,
+            _ => return Err(bad_variant()),
+        }
+    }
+    match config.is_valid() && config.fits_notation() {
+        true => Ok((config, rules)),
+        false => Err(bad_variant()),
+    }
+}
+
+fn write_tag(f: &mut fmt::Formatter<'_>, name: &str, value: &str) -> fmt::Result {
+    writeln!(f, "[{} \"{}\"]", name, value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Splits `[Name "value"]` line into name and unescaped value
+fn parse_tag(line: &str) -> Result<(&str, String), NotationError> {
+    let bad_tag = || NotationError::BadTag(line.to_string());
+    let inner = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')).ok_or_else(bad_tag)?;
+    let (name, quoted) = inner.split_once(' ').ok_or_else(bad_tag)?;
+    let quoted = quoted.trim().strip_prefix('"').and_then(|value| value.strip_suffix('"')).ok_or_else(bad_tag)?;
+    let mut value = String::new();
+    let mut chars = quoted.chars();
+    while let Some(char) = chars.next() {
+        match char {
+            '\\' => value.push(chars.next().ok_or_else(bad_tag)?),
+            '"' => return Err(bad_tag()),
+            _ => value.push(char),
+        }
+    }
+    Ok((name, value))
+}
+
+impl fmt::Display for GameRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_tag(f, "Variant", &variant_text(&self.config, &self.rules))?;
+        write_tag(f, "X", &self.x_player)?;
+        write_tag(f, "O", &self.o_player)?;
+        write_tag(f, "Result", &self.result.to_string())?;
+        write_tag(f, "Date", &self.date)?;
+        if let Some(position) = &self.position {
+            write_tag(f, "Position", position)?;
+        }
+        writeln!(f)?;
+        for (index, mv) in self.moves.iter().enumerate() {
+            let separator = match index {
+                0 => "",
+                _ if index % MOVES_PER_LINE == 0 => "\n",
+                _ => " ",
+            };
+            write!(f, "{}{}. {}", separator, index + 1, mv.notation(&self.config))?;
+        }
+        if !self.moves.is_empty() {
+            write!(f, " ")?;
+        }
+        writeln!(f, "{}", self.result)
+    }
+}
+
+impl FromStr for GameRecord {
+    type Err = NotationError;
+
+    /// Reads record, unknown tags are skipped and the result after moves wins over Result tag
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut variant = None;
+        let mut x_player = String::new();
+        let mut o_player = String::new();
+        let mut result = RecordResult::Unfinished;
+        let mut date = String::new();
+        let mut position = None;
+        let mut move_text = String::new();
+        for line in text.lines().map(str::trim) {
+            if line.starts_with('[') {
+                let (name, value) = parse_tag(line)?;
+                match name {
+                    "Variant" => variant = Some(parse_variant(&value)?),
+                    "X" => x_player = value,
+                    "O" => o_player = value,
+                    "Result" => result = value.parse()?,
+                    "Date" => date = value,
+                    "Position" => position = Some(value),
+                    _ => (),
+                }
+            } else {
+                move_text.push_str(line);
+                move_text.push(' ');
+            }
+        }
+        let (config, rules) = variant.ok_or_else(|| NotationError::BadVariant(String::new()))?;
+        let mut moves = Vec::new();
+        for token in move_text.split_whitespace() {
+            if token.ends_with('.') && token[..token.len() - 1].parse::<usize>().is_ok() {
+                continue;
+            }
+            match Move::from_notation(token, &config) {
+                Some(mv) => moves.push(mv),
+                None => result = token.parse().map_err(|_| NotationError::BadMove(token.to_string()))?,
+            }
+        }
+        Ok(GameRecord {
+            config,
+            rules,
+            x_player,
+            o_player,
+            result,
+            date,
+            position,
+            moves,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::play_legal;
+
+    #[test]
+    fn finished_game_round_trips() {
+        let mut game = Game::default();
+        play_legal(&mut game, 0, 100);
+        assert!(game.outcome().is_some());
+        let record = GameRecord::from_game(&game, "Ann \"the wall\"", r"C:\players\bob", "2024.05.01");
+        let parsed: GameRecord = record.to_string().parse().unwrap();
+        assert_eq!(parsed, record);
+        assert_eq!(parsed.to_game().unwrap().position(), game.position());
+    }
+
+    #[test]
+    fn game_from_position_round_trips() {
+        let rules = Rules { finished_board: FinishedBoardRule::WonBoardsDrawOnly, misere: true, ..Rules::default() };
+        let mut start = Game::new(GridConfig::default(), rules);
+        play_legal(&mut start, 0, 12);
+        let mut game = Game::from_position(&start.position(), rules).unwrap();
+        play_legal(&mut game, 0, 7);
+        let record = GameRecord::from_game(&game, "X", "O", "2024.05.01");
+        assert_eq!(record.position, Some(start.position()));
+        assert_eq!(record.result, RecordResult::Unfinished);
+        let text = record.to_string();
+        assert!(text.contains("[Position \""));
+        let parsed: GameRecord = text.parse().unwrap();
+        assert_eq!(parsed, record);
+        let replayed = parsed.to_game().unwrap();
+        assert_eq!(replayed.position(), game.position());
+        assert_eq!(replayed.history(), game.history());
+    }
+
+    #[test]
+    fn tag_values_are_unescaped() {
+        assert_eq!(parse_tag(r#"[X "say \"hi\" \\o/"]"#), Ok(("X", r#"say "hi" \o/"#.to_string())));
+        assert!(matches!(parse_tag(r#"[X "un"escaped"]"#), Err(NotationError::BadTag(_))));
+        assert!(matches!(parse_tag(r#"[X "dangling\"]"#), Err(NotationError::BadTag(_))));
+        assert!(matches!(parse_tag(r#"[X unquoted]"#), Err(NotationError::BadTag(_))));
+    }
+
+    #[test]
+    fn malformed_variants_are_rejected() {
+        for variant in ["board=three", "board=3 colour=red", "board=3 win=5", "finished=sometimes", "misere", "depth=9"] {
+            let text = format!("[Variant \"{}\"]\n\n*\n", variant);
+            assert!(matches!(text.parse::<GameRecord>(), Err(NotationError::BadVariant(_))), "{}", variant);
+        }
+        assert!(matches!("[X \"Ann\"]\n\n*\n".parse::<GameRecord>(), Err(NotationError::BadVariant(_))));
+    }
+}
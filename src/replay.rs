@@ -0,0 +1,132 @@
+use bevy::prelude::*;
+use bevy_egui::{egui::{self, Align2}, EguiContexts};
+
+use crate::{network::client::{CurrentGame, Preview, ShownGame}, GameState};
+
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ReplayControls>()
+        .add_systems(OnEnter(GameState::Replaying), start_replay)
+        .add_systems(Update, (replay_keyboard, replay_autoplay, replay_ui_system).chain().run_if(in_state(GameState::Replaying)));
+    }
+}
+
+/// Autoplay settings of replay viewer
+#[derive(Resource)]
+struct ReplayControls {
+    autoplay: bool,
+    moves_per_second: f32,
+    /// Seconds since autoplay made last step
+    elapsed: f32,
+}
+
+impl Default for ReplayControls {
+    fn default() -> Self {
+        ReplayControls {
+            autoplay: false,
+            moves_per_second: 1.,
+            elapsed: 0.,
+        }
+    }
+}
+
+/// Replay starts from empty board
+fn start_replay(mut preview: ResMut<Preview>, mut controls: ResMut<ReplayControls>) {
+    preview.0 = Some(0);
+    controls.autoplay = false;
+    controls.elapsed = 0.;
+}
+
+/// Moves shown in replay after a step, kept between start and end of the game
+fn step(preview: &Preview, game: &CurrentGame, by: isize) -> usize {
+    let shown = preview.0.unwrap_or(0) as isize + by;
+    shown.clamp(0, game.0.history().len() as isize) as usize
+}
+
+/// Left/Right step one move, Home/End jump to start/end, Space toggles autoplay
+fn replay_keyboard(
+    keys: Res<ButtonInput<KeyCode>>,
+    game: Res<CurrentGame>,
+    mut preview: ResMut<Preview>,
+    mut controls: ResMut<ReplayControls>,
+) {
+    if keys.just_pressed(KeyCode::ArrowLeft) {
+        preview.0 = Some(step(&preview, &game, -1));
+    }
+    if keys.just_pressed(KeyCode::ArrowRight) {
+        preview.0 = Some(step(&preview, &game, 1));
+    }
+    if keys.just_pressed(KeyCode::Home) {
+        preview.0 = Some(0);
+    }
+    if keys.just_pressed(KeyCode::End) {
+        preview.0 = Some(game.0.history().len());
+    }
+    if keys.just_pressed(KeyCode::Space) {
+        controls.autoplay = !controls.autoplay;
+    }
+}
+
+fn replay_autoplay(
+    time: Res<Time>,
+    game: Res<CurrentGame>,
+    mut preview: ResMut<Preview>,
+    mut controls: ResMut<ReplayControls>,
+) {
+    if !controls.autoplay {
+        controls.elapsed = 0.;
+        return;
+    }
+    controls.elapsed += time.delta_seconds();
+    if controls.elapsed < 1. / controls.moves_per_second {
+        return;
+    }
+    controls.elapsed = 0.;
+    if preview.0 == Some(game.0.history().len()) {
+        controls.autoplay = false;
+    } else {
+        preview.0 = Some(step(&preview, &game, 1));
+    }
+}
+
+fn replay_ui_system(
+    mut contexts: EguiContexts,
+    game: Res<CurrentGame>,
+    shown_game: Res<ShownGame>,
+    mut preview: ResMut<Preview>,
+    mut controls: ResMut<ReplayControls>,
+    mut next_game_state: ResMut<NextState<GameState>>,
+) {
+    let moves = game.0.history().len();
+    egui::Window::new("Replay").anchor(Align2::LEFT_TOP, [0.,0.]).show(contexts.ctx_mut(), |ui| {
+        ui.label(format!("Move {} / {}", preview.0.unwrap_or(0), moves));
+        ui.horizontal(|ui| {
+            if ui.button("|<").clicked() {
+                preview.0 = Some(0);
+            }
+            if ui.button("<").clicked() {
+                preview.0 = Some(step(&preview, &game, -1));
+            }
+            if ui.button(">").clicked() {
+                preview.0 = Some(step(&preview, &game, 1));
+            }
+            if ui.button(">|").clicked() {
+                preview.0 = Some(moves);
+            }
+        });
+        ui.checkbox(&mut controls.autoplay, "Autoplay");
+        ui.add(egui::Slider::new(&mut controls.moves_per_second, 0.25..=10.).text("Moves per second"));
+        ui.label("Keys: Left/Right step, Home/End jump, Space autoplay");
+        if let Some(outcome) = shown_game.0.outcome() {
+            ui.label(match outcome.winner() {
+                Some(player) => format!("WINNER:{}", player),
+                None => "Draw".to_string(),
+            });
+        }
+        if ui.button("Back to menu").clicked() {
+            next_game_state.set(GameState::FinishingGame);
+        }
+    });
+}
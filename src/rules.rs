@@ -0,0 +1,1193 @@
+//! Ultimate tic-tac-toe rules without any Bevy types
+//!
+//! Boards are stored as flat arrays indexed by `y * size + x`, where (0,0) is the bottom left cell.
+//! Bots, server and tests can play whole games here, grid systems only mirror it into entities.
+
+use serde::{Deserialize, Serialize};
+
+/// Deepest board nesting a move can address
+pub const MAX_DEPTH: usize = 4;
+/// Most cells a game may have, every cell is an entity with a sprite. Fits 3x3 boards 4 levels deep
+pub const MAX_CELLS: usize = 6561;
+
+/// Sizes of boards, nesting depth and length of winning line
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct GridConfig {
+    /// Side length of every local board
+    pub board_size: usize,
+    /// Side length of main board, the amount of local boards in a row
+    pub outer_size: usize,
+    /// Marks in a row needed to take a board
+    pub win_length: usize,
+    /// Levels of boards including the main board, 2 for ultimate tic-tac-toe
+    pub depth: usize,
+}
+
+impl Default for GridConfig {
+    fn default() -> Self {
+        GridConfig {
+            board_size: 3,
+            outer_size: 3,
+            win_length: 3,
+            depth: 2,
+        }
+    }
+}
+
+impl GridConfig {
+    /// True if a line can be made on every board
+    pub fn is_valid(&self) -> bool {
+        (1..=MAX_DEPTH).contains(&self.depth)
+            && self.board_size > 0
+            && self.outer_size > 0
+            && self.win_length > 0
+            && self.win_length <= self.board_size
+            && (self.depth == 1 || self.win_length <= self.outer_size)
+            && self.total_cells() <= MAX_CELLS
+    }
+    /// Amount of cells that get marks, in every local board together
+    pub fn total_cells(&self) -> usize {
+        (0..self.depth).map(|level| self.level_cells(level)).product()
+    }
+    /// Deepest nesting these board sizes can have without going over `MAX_CELLS`
+    pub fn max_depth(&self) -> usize {
+        (1..=MAX_DEPTH)
+            .rev()
+            .find(|depth| GridConfig { depth: *depth, ..*self }.total_cells() <= MAX_CELLS)
+            .unwrap_or(1)
+    }
+    /// True if every cell can be written in move notation, letters only go up to 52 cells per board
+    pub fn fits_notation(&self) -> bool {
+        self.board_size.max(self.outer_size).pow(2) <= 52
+    }
+    /// Side length of boards on given nesting level, level 0 is the main board
+    pub fn level_size(&self, level: usize) -> usize {
+        if level + 1 == self.depth {
+            self.board_size
+        } else {
+            self.outer_size
+        }
+    }
+    /// Amount of cells in boards on given nesting level
+    pub fn level_cells(&self, level: usize) -> usize {
+        self.level_size(level) * self.level_size(level)
+    }
+    /// Amount of local boards
+    pub fn outer_cells(&self) -> usize {
+        self.outer_size * self.outer_size
+    }
+}
+
+/// What happens with boards that are already won
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum FinishedBoardRule {
+    /// Won boards are closed, being sent to a won or full board gives a free move
+    #[default]
+    FreeMove,
+    /// Won boards can be played in until they are full and keep their winner
+    PlayInWonBoards,
+    /// Won boards can be played in, but a line of the other player only turns them into a draw
+    WonBoardsDrawOnly,
+}
+
+impl FinishedBoardRule {
+    /// Every rule, in the order menu shows them
+    pub const ALL: [FinishedBoardRule; 3] = [
+        FinishedBoardRule::FreeMove,
+        FinishedBoardRule::PlayInWonBoards,
+        FinishedBoardRule::WonBoardsDrawOnly,
+    ];
+    /// Short description for menus
+    pub fn name(&self) -> &'static str {
+        match self {
+            FinishedBoardRule::FreeMove => "Free move when sent to a won or full board",
+            FinishedBoardRule::PlayInWonBoards => "May still play inside won boards",
+            FinishedBoardRule::WonBoardsDrawOnly => "Won boards stay playable for draws only",
+        }
+    }
+}
+
+/// Rule variants both players agree on before the game
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Rules {
+    pub finished_board: FinishedBoardRule,
+    /// Game without a line on main board is won by whoever won more local boards
+    pub majority_tiebreak: bool,
+    /// Player who makes a line on main board loses, majority tiebreak still goes to more won boards
+    pub misere: bool,
+    /// Hints each player may ask the engine for during one game
+    pub hints_per_game: usize,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {
+            finished_board: FinishedBoardRule::default(),
+            majority_tiebreak: false,
+            misere: false,
+            hints_per_game: 3,
+        }
+    }
+}
+
+/// Directions a line can go in
+pub(crate) const DIRECTIONS: [(isize, isize); 4] = [(1, 0), (0, 1), (1, 1), (1, -1)];
+
+/// Player of the game
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Player {
+    X,
+    O,
+}
+
+impl Player {
+    /// The opposing player
+    pub fn other(self) -> Player {
+        match self {
+            Player::X => Player::O,
+            Player::O => Player::X,
+        }
+    }
+}
+
+impl std::fmt::Display for Player {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Player::X => write!(f, "X"),
+            Player::O => write!(f, "O"),
+        }
+    }
+}
+
+/// Player that starts every game
+pub const FIRST_PLAYER: Player = Player::O;
+
+/// State of a local board
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BoardState {
+    /// Can still be played in
+    Open,
+    /// Someone made a line here
+    Won(Player),
+    /// Filled without a line
+    Drawn,
+}
+
+/// Result of finished game
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// Player made a line on main board
+    Line(Player),
+    /// Player made a line on main board in misère game and lost
+    MisereLine(Player),
+    /// Main board was filled without a line and player won more local boards
+    Boards(Player),
+    /// Every local board is finished and nobody won
+    Draw,
+}
+
+impl Outcome {
+    /// Player who won the game, None for a draw
+    pub fn winner(&self) -> Option<Player> {
+        match self {
+            Outcome::Line(player) | Outcome::Boards(player) => Some(*player),
+            Outcome::MisereLine(player) => Some(player.other()),
+            Outcome::Draw => None,
+        }
+    }
+}
+
+/// Reason a move can't be made
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MoveError {
+    /// Move was made by the player who is waiting for the opponent
+    NotYourTurn,
+    /// Move is outside of the board player was sent to, or in a board that is already finished
+    WrongBoard,
+    /// Cell already has a mark
+    Occupied,
+    /// Game already has a result
+    GameOver,
+    /// Path doesn't address a cell of this game
+    OutOfRange,
+}
+
+impl std::fmt::Display for MoveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            MoveError::NotYourTurn => "it is not your turn",
+            MoveError::WrongBoard => "you have to play in the highlighted board",
+            MoveError::Occupied => "this cell is already taken",
+            MoveError::GameOver => "the game is over",
+            MoveError::OutOfRange => "there is no such cell",
+        })
+    }
+}
+
+impl std::error::Error for MoveError {}
+
+/// Move of current player
+///
+/// Addressed by a path of cell indices, from the main board down to the cell that gets the mark
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Move {
+    path: [usize; MAX_DEPTH],
+    len: usize,
+}
+
+impl Move {
+    /// Creates move from path of cell indices, path must not be longer than `MAX_DEPTH`
+    pub fn new(path: &[usize]) -> Move {
+        let mut mv = Move {
+            path: [0; MAX_DEPTH],
+            len: path.len(),
+        };
+        mv.path[..path.len()].copy_from_slice(path);
+        mv
+    }
+    /// Cell indices from the main board down to the marked cell
+    pub fn path(&self) -> &[usize] {
+        &self.path[..self.len]
+    }
+    /// Readable name of the move like `e/c3`
+    ///
+    /// Outer boards are lettered by index (`a` is bottom left, `A` follows `z`),
+    /// marked cell is column letter and row number counted from the bottom
+    pub fn notation(&self, config: &GridConfig) -> String {
+        let mut parts: Vec<String> = Vec::new();
+        for (level, index) in self.path().iter().enumerate() {
+            let size = config.level_size(level);
+            if level + 1 < self.len {
+                parts.push(board_letter(*index).to_string());
+            } else {
+                parts.push(format!("{}{}", board_letter(index % size), index / size + 1));
+            }
+        }
+        parts.join("/")
+    }
+    /// Reads move written by `notation`, None if it doesn't address a cell of this config
+    pub fn from_notation(text: &str, config: &GridConfig) -> Option<Move> {
+        let parts: Vec<&str> = text.split('/').collect();
+        if parts.len() != config.depth {
+            return None;
+        }
+        let mut path = Vec::with_capacity(parts.len());
+        for (level, part) in parts.iter().enumerate() {
+            let size = config.level_size(level);
+            let mut chars = part.chars();
+            let index = letter_index(chars.next()?)?;
+            if level + 1 < parts.len() {
+                if chars.next().is_some() || index >= size * size {
+                    return None;
+                }
+                path.push(index);
+            } else {
+                let row: usize = chars.as_str().parse().ok()?;
+                if index >= size || row == 0 || row > size {
+                    return None;
+                }
+                path.push((row - 1) * size + index);
+            }
+        }
+        Some(Move::new(&path))
+    }
+}
+
+/// Letter of a cell index used in move notation
+fn board_letter(index: usize) -> char {
+    match index {
+        0..=25 => (b'a' + index as u8) as char,
+        _ => (b'A' + (index - 26) as u8) as char,
+    }
+}
+
+/// Character of a cell in position string
+fn state_char(state: BoardState) -> char {
+    match state {
+        BoardState::Open => '.',
+        BoardState::Won(Player::X) => 'x',
+        BoardState::Won(Player::O) => 'o',
+        BoardState::Drawn => '-',
+    }
+}
+
+/// Cell state of a character in position string
+fn char_state(char: char) -> Option<BoardState> {
+    match char {
+        '.' => Some(BoardState::Open),
+        'x' => Some(BoardState::Won(Player::X)),
+        'o' => Some(BoardState::Won(Player::O)),
+        '-' => Some(BoardState::Drawn),
+        _ => None,
+    }
+}
+
+/// Reads sizes of position string like `b3o3w3d2`
+fn parse_sizes(text: &str) -> Option<GridConfig> {
+    let rest = text.strip_prefix('b')?;
+    let (board_size, rest) = rest.split_once('o')?;
+    let (outer_size, rest) = rest.split_once('w')?;
+    let (win_length, depth) = rest.split_once('d')?;
+    let config = GridConfig {
+        board_size: board_size.parse().ok()?,
+        outer_size: outer_size.parse().ok()?,
+        win_length: win_length.parse().ok()?,
+        depth: depth.parse().ok()?,
+    };
+    (config.is_valid() && config.fits_notation()).then_some(config)
+}
+
+/// Cell index of a letter used in move notation
+fn letter_index(letter: char) -> Option<usize> {
+    match letter {
+        'a'..='z' => Some(letter as usize - 'a' as usize),
+        'A'..='Z' => Some(letter as usize - 'A' as usize + 26),
+        _ => None,
+    }
+}
+
+/// Line of marks that won a board
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Line {
+    /// Index of the first cell of the line
+    pub start: usize,
+    /// Index of the last cell of the line
+    pub end: usize,
+    pub owner: Player,
+}
+
+/// One square board, cells of leaf boards are marks and cells of other boards are nested boards
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Board {
+    size: usize,
+    cells: Vec<BoardState>,
+    /// Line that won this board
+    line: Option<Line>,
+    /// True if an empty cell can still be reached inside, `Game` keeps it up to date
+    has_moves: bool,
+}
+
+impl Board {
+    /// Creates empty board with given side length
+    pub fn new(size: usize) -> Board {
+        Board {
+            size,
+            cells: vec![BoardState::Open; size * size],
+            line: None,
+            has_moves: size > 0,
+        }
+    }
+    /// Side length of the board
+    pub fn size(&self) -> usize {
+        self.size
+    }
+    /// State of the cell, `Won` is a mark for cells of leaf boards
+    pub fn get(&self, cell: usize) -> BoardState {
+        self.cells[cell]
+    }
+    /// True if there are no open cells left
+    pub fn is_full(&self) -> bool {
+        self.cells.iter().all(|cell| *cell != BoardState::Open)
+    }
+    /// Line that won this board, None for open and drawn boards
+    pub fn line(&self) -> Option<Line> {
+        self.line
+    }
+    /// First line of `win_length` marks in a row player made on this board
+    pub fn find_line(&self, win_length: usize, player: Player) -> Option<Line> {
+        let size = self.size as isize;
+        let is_owner = |x: isize, y: isize| {
+            (0..size).contains(&x)
+                && (0..size).contains(&y)
+                && self.cells[(y * size + x) as usize] == BoardState::Won(player)
+        };
+        (0..self.cells.len()).find_map(|start| {
+            let (x, y) = ((start % self.size) as isize, (start / self.size) as isize);
+            DIRECTIONS.iter().find_map(|(dx, dy)| {
+                let last = win_length as isize - 1;
+                (0..win_length as isize).all(|step| is_owner(x + dx * step, y + dy * step)).then(|| Line {
+                    start,
+                    end: ((y + dy * last) * size + x + dx * last) as usize,
+                    owner: player,
+                })
+            })
+        })
+    }
+    /// True if player made `win_length` marks in a row on this board
+    pub fn has_line(&self, win_length: usize, player: Player) -> bool {
+        self.find_line(win_length, player).is_some()
+    }
+    /// Player who made `win_length` marks in a row on this board
+    pub fn line_owner(&self, win_length: usize) -> Option<Player> {
+        [Player::X, Player::O].into_iter().find(|player| self.has_line(win_length, *player))
+    }
+    /// True if player has a line here, or had one before some of its cells turned into draws
+    fn had_line(&self, win_length: usize, player: Player) -> bool {
+        let mut board = self.clone();
+        for cell in &mut board.cells {
+            if *cell == BoardState::Drawn {
+                *cell = BoardState::Won(player);
+            }
+        }
+        board.has_line(win_length, player)
+    }
+    /// True if this board can have given state under the rule, whatever order marks were made in
+    fn allows_state(&self, state: BoardState, win_length: usize, rule: FinishedBoardRule) -> bool {
+        let x_line = self.has_line(win_length, Player::X);
+        let o_line = self.has_line(win_length, Player::O);
+        // won cells of nested boards can later turn into draws, boards keep results their lines gave them
+        let had_line = |player: Player| match rule {
+            FinishedBoardRule::WonBoardsDrawOnly => self.had_line(win_length, player),
+            _ => self.has_line(win_length, player),
+        };
+        match state {
+            BoardState::Open => !x_line && !o_line && !self.is_full(),
+            // the first line keeps the board only when the other player can play on in it
+            BoardState::Won(owner) => {
+                had_line(owner)
+                    && (rule == FinishedBoardRule::PlayInWonBoards || !self.has_line(win_length, owner.other()))
+            },
+            BoardState::Drawn => {
+                (!x_line && !o_line && self.is_full())
+                    || (rule == FinishedBoardRule::WonBoardsDrawOnly && had_line(Player::X) && had_line(Player::O))
+            },
+        }
+    }
+    /// True if player has `win_length` marks in a row going through the cell
+    fn has_line_through(&self, cell: usize, win_length: usize, player: Player) -> bool {
+        let size = self.size as isize;
+        let (x, y) = ((cell % self.size) as isize, (cell / self.size) as isize);
+        let run = |dx: isize, dy: isize| {
+            (1..win_length as isize)
+                .take_while(|step| {
+                    let (x, y) = (x + dx * step, y + dy * step);
+                    (0..size).contains(&x) && (0..size).contains(&y) && self.cells[(y * size + x) as usize] == BoardState::Won(player)
+                })
+                .count()
+        };
+        self.cells[cell] == BoardState::Won(player)
+            && DIRECTIONS.iter().any(|(dx, dy)| 1 + run(*dx, *dy) + run(-dx, -dy) >= win_length)
+    }
+    /// State this board gets after given cell changed
+    ///
+    /// A new line has to go through that cell, so only lines through it are looked for
+    fn next_state(&self, state: BoardState, cell: usize, win_length: usize, rule: FinishedBoardRule) -> BoardState {
+        match (state, self.cells[cell]) {
+            (BoardState::Open, BoardState::Won(owner)) if self.has_line_through(cell, win_length, owner) => BoardState::Won(owner),
+            (BoardState::Open, _) if self.is_full() => BoardState::Drawn,
+            (BoardState::Won(owner), BoardState::Won(other))
+                if rule == FinishedBoardRule::WonBoardsDrawOnly
+                    && other != owner
+                    && self.has_line_through(cell, win_length, other) =>
+            {
+                BoardState::Drawn
+            },
+            (state, _) => state,
+        }
+    }
+}
+
+/// Full game position
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Game {
+    config: GridConfig,
+    rules: Rules,
+    /// Boards of every nesting level, first level only holds the main board
+    levels: Vec<Vec<Board>>,
+    /// State of the main board
+    state: BoardState,
+    to_move: Player,
+    /// Path of the board where next move must be made, empty if any board can be used
+    forced_board: Vec<usize>,
+    /// Every move made, in order
+    history: Vec<Move>,
+    /// Moves taken back by undo, the last one is redone first
+    undone: Vec<Move>,
+    /// Position the game started from when it wasn't the empty board
+    start: Option<Box<Game>>,
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Game::new(GridConfig::default(), Rules::default())
+    }
+}
+
+impl Game {
+    /// Creates empty game
+    pub fn new(config: GridConfig, rules: Rules) -> Game {
+        let levels = (0..config.depth)
+            .map(|level| vec![Board::new(config.level_size(level)); config.outer_cells().pow(level as u32)])
+            .collect();
+        Game {
+            config,
+            rules,
+            levels,
+            state: BoardState::Open,
+            to_move: FIRST_PLAYER,
+            forced_board: Vec::new(),
+            history: Vec::new(),
+            undone: Vec::new(),
+            start: None,
+        }
+    }
+    /// Rebuilds game by making moves from the start
+    pub fn from_history(config: GridConfig, rules: Rules, history: &[Move]) -> Result<Game, MoveError> {
+        let mut game = Game::new(config, rules);
+        for mv in history {
+            game.apply(*mv)?;
+        }
+        Ok(game)
+    }
+    /// Sizes this game is played with
+    pub fn config(&self) -> GridConfig {
+        self.config
+    }
+    /// Rule variants this game is played with
+    pub fn rules(&self) -> Rules {
+        self.rules
+    }
+    /// Index of the board with given path among boards of its level
+    fn board_index(&self, path: &[usize]) -> usize {
+        path.iter().fold(0, |index, cell| index * self.config.outer_cells() + cell)
+    }
+    /// Board with given path, empty path is the main board
+    pub fn board(&self, path: &[usize]) -> &Board {
+        &self.levels[path.len()][self.board_index(path)]
+    }
+    /// State of the board with given path, empty path is the main board
+    pub fn board_state(&self, path: &[usize]) -> BoardState {
+        match path.split_last() {
+            Some((cell, parent)) => self.board(parent).get(*cell),
+            None => self.state,
+        }
+    }
+    /// Line that won the board with given path, empty path is the main board
+    pub fn line(&self, path: &[usize]) -> Option<Line> {
+        self.board(path).line()
+    }
+    /// Owner of the cell with full path
+    pub fn cell(&self, path: &[usize]) -> Option<Player> {
+        match self.board_state(path) {
+            BoardState::Won(owner) => Some(owner),
+            _ => None,
+        }
+    }
+    /// Player making the next move
+    pub fn to_move(&self) -> Player {
+        self.to_move
+    }
+    /// Path of the board where the next move must be made, empty if any open board can be used
+    pub fn forced_board(&self) -> &[usize] {
+        &self.forced_board
+    }
+    /// True if cell or board with given state on given level can get marks, ignoring boards containing it
+    fn accepts(&self, state: BoardState, level: usize) -> bool {
+        match state {
+            BoardState::Open => true,
+            BoardState::Won(_) => level < self.config.depth && self.rules.finished_board != FinishedBoardRule::FreeMove,
+            BoardState::Drawn => false,
+        }
+    }
+    /// True if cell or board with given path can get marks, ignoring boards containing it
+    fn accepts_moves(&self, path: &[usize]) -> bool {
+        self.accepts(self.board_state(path), path.len())
+    }
+    /// True if there is an empty cell reachable inside of the board
+    fn has_moves(&self, path: &[usize]) -> bool {
+        path.len() == self.config.depth || self.board(path).has_moves
+    }
+    /// Works out again if board with given index among boards of its level has moves, boards inside of it
+    /// have to be up to date
+    fn update_has_moves(&mut self, level: usize, index: usize) {
+        let board = &self.levels[level][index];
+        let is_leaf = level + 1 == self.config.depth;
+        let has_moves = board.cells.iter().enumerate().any(|(cell, state)| {
+            self.accepts(*state, level + 1)
+                && (is_leaf || self.levels[level + 1][index * self.config.outer_cells() + cell].has_moves)
+        });
+        self.levels[level][index].has_moves = has_moves;
+    }
+    /// True if board and every board containing it can still receive moves
+    fn is_playable(&self, path: &[usize]) -> bool {
+        self.state == BoardState::Open
+            && (1..=path.len()).all(|len| self.accepts_moves(&path[..len]))
+            && self.has_moves(path)
+    }
+    /// Path of the board the opponent is sent to after a move
+    ///
+    /// Every level sends to the board matching the cell below it, so only the main board choice is lost.
+    /// Leaf cell coordinates are scaled when local and outer boards have different sizes
+    fn target_board(&self, path: &[usize]) -> Vec<usize> {
+        let GridConfig { board_size, outer_size, .. } = self.config;
+        let mut target = path[1..].to_vec();
+        if let Some(cell) = target.last_mut() {
+            let (x, y) = (*cell % board_size, *cell / board_size);
+            *cell = (y * outer_size / board_size) * outer_size + x * outer_size / board_size;
+        }
+        // falls back to the closest board containing the target that can still be played in
+        let playable = (0..=target.len()).rev().find(|&len| self.is_playable(&target[..len])).unwrap_or(0);
+        target.truncate(playable);
+        target
+    }
+    /// Checks move of current player against all rules
+    pub fn validate(&self, mv: Move) -> Result<(), MoveError> {
+        let path = mv.path();
+        if self.state != BoardState::Open {
+            return Err(MoveError::GameOver);
+        }
+        if path.len() != self.config.depth
+            || path.iter().enumerate().any(|(level, cell)| *cell >= self.config.level_cells(level))
+        {
+            return Err(MoveError::OutOfRange);
+        }
+        let (_, board) = path.split_last().unwrap();
+        if !path.starts_with(&self.forced_board) || !self.is_playable(board) {
+            return Err(MoveError::WrongBoard);
+        }
+        if !self.accepts_moves(path) {
+            return Err(MoveError::Occupied);
+        }
+        Ok(())
+    }
+    /// Checks move of given player against all rules, moves out of turn are refused before looking at the cell
+    pub fn validate_as(&self, player: Player, mv: Move) -> Result<(), MoveError> {
+        if self.state != BoardState::Open {
+            return Err(MoveError::GameOver);
+        }
+        if player != self.to_move {
+            return Err(MoveError::NotYourTurn);
+        }
+        self.validate(mv)
+    }
+    /// True if current player can make the move
+    pub fn is_legal(&self, mv: Move) -> bool {
+        self.validate(mv).is_ok()
+    }
+    /// All moves current player can make
+    pub fn legal_moves(&self) -> Vec<Move> {
+        let mut moves = Vec::new();
+        let mut path = self.forced_board.clone();
+        if self.is_playable(&path) {
+            self.collect_moves(&mut path, &mut moves);
+        }
+        moves
+    }
+    /// Adds every move inside of the playable board with given path
+    fn collect_moves(&self, path: &mut Vec<usize>, moves: &mut Vec<Move>) {
+        let level = path.len();
+        let index = self.board_index(path);
+        for (cell, state) in self.levels[level][index].cells.iter().enumerate() {
+            if !self.accepts(*state, level + 1) {
+                continue;
+            }
+            path.push(cell);
+            if level + 1 == self.config.depth {
+                moves.push(Move::new(path));
+            } else if self.levels[level + 1][index * self.config.outer_cells() + cell].has_moves {
+                self.collect_moves(path, moves);
+            }
+            path.pop();
+        }
+    }
+    /// Makes move for given player, game is left untouched if move is illegal
+    pub fn apply_as(&mut self, player: Player, mv: Move) -> Result<(), MoveError> {
+        self.validate_as(player, mv)?;
+        self.apply(mv)
+    }
+    /// Makes move for current player, game is left untouched if move is illegal
+    pub fn apply(&mut self, mv: Move) -> Result<(), MoveError> {
+        self.validate(mv)?;
+        let path = mv.path();
+        self.history.push(mv);
+        self.undone.clear();
+        let mut state = BoardState::Won(self.to_move);
+        // the mark is stored in its leaf board, then every board that changed reports to its parent
+        for level in (0..path.len()).rev() {
+            let old_state = self.board_state(&path[..level]);
+            let index = self.board_index(&path[..level]);
+            let board = &mut self.levels[level][index];
+            board.cells[path[level]] = state;
+            state = board.next_state(old_state, path[level], self.config.win_length, self.rules.finished_board);
+            if state == old_state {
+                break;
+            }
+            board.line = match state {
+                BoardState::Won(owner) => board.find_line(self.config.win_length, owner),
+                _ => None,
+            };
+            if level == 0 {
+                self.state = state;
+            }
+        }
+        // only boards holding the new mark can run out of moves
+        for level in (0..path.len()).rev() {
+            self.update_has_moves(level, self.board_index(&path[..level]));
+        }
+        self.forced_board = self.target_board(path);
+        self.to_move = self.to_move.other();
+        Ok(())
+    }
+    /// Copy of the position without moves that led to it, so searches can clone it cheaply for every line
+    ///
+    /// The copy can't undo or replay moves made before it
+    pub fn without_history(&self) -> Game {
+        Game {
+            config: self.config,
+            rules: self.rules,
+            levels: self.levels.clone(),
+            state: self.state,
+            to_move: self.to_move,
+            forced_board: self.forced_board.clone(),
+            history: Vec::new(),
+            undone: Vec::new(),
+            start: None,
+        }
+    }
+    /// Every move made, in order
+    pub fn history(&self) -> &[Move] {
+        &self.history
+    }
+    /// True if there is a move to take back
+    pub fn can_undo(&self) -> bool {
+        !self.history.is_empty()
+    }
+    /// True if there is an undone move to make again
+    pub fn can_redo(&self) -> bool {
+        !self.undone.is_empty()
+    }
+    /// Player who made the first move of history
+    pub fn start_player(&self) -> Player {
+        self.start.as_ref().map_or(FIRST_PLAYER, |start| start.to_move)
+    }
+    /// Position after the first `moves` moves of history, without undone moves
+    pub fn replayed(&self, moves: usize) -> Game {
+        let mut game = match &self.start {
+            Some(start) => (**start).clone(),
+            None => Game::new(self.config, self.rules),
+        };
+        for mv in &self.history[..moves] {
+            game.apply(*mv).expect("moves in history are legal");
+        }
+        game.start = self.start.clone();
+        game
+    }
+    /// Takes back the last move, the position is rebuilt from history
+    pub fn undo(&mut self) -> Option<Move> {
+        let mv = *self.history.last()?;
+        let mut undone = std::mem::take(&mut self.undone);
+        *self = self.replayed(self.history.len() - 1);
+        undone.push(mv);
+        self.undone = undone;
+        Some(mv)
+    }
+    /// Makes the last undone move again
+    pub fn redo(&mut self) -> Option<Move> {
+        let mut undone = std::mem::take(&mut self.undone);
+        let mv = undone.pop()?;
+        self.apply(mv).expect("undone moves are legal");
+        self.undone = undone;
+        Some(mv)
+    }
+    /// One line position string with sizes, every cell, board results, side to move and forced board
+    ///
+    /// `b3o3w3d2 <leaf boards> <results of upper levels> <x|o> <forced board|->`, boards are separated
+    /// by `/`, levels by `|`, cells are `.` open, `x` or `o` owner and `-` drawn. Rules aren't included
+    pub fn position(&self) -> String {
+        let GridConfig { board_size, outer_size, win_length, depth } = self.config;
+        let level_text = |level: &Vec<Board>| {
+            level
+                .iter()
+                .map(|board| board.cells.iter().map(|cell| state_char(*cell)).collect::<String>())
+                .collect::<Vec<String>>()
+                .join("/")
+        };
+        let results = match depth {
+            1 => "-".to_string(),
+            _ => self.levels[..depth - 1].iter().map(level_text).collect::<Vec<String>>().join("|"),
+        };
+        let forced = match self.forced_board.is_empty() {
+            true => "-".to_string(),
+            false => self.forced_board.iter().map(|cell| board_letter(*cell).to_string()).collect::<Vec<String>>().join("/"),
+        };
+        let to_move = match self.to_move {
+            Player::X => 'x',
+            Player::O => 'o',
+        };
+        format!(
+            "b{}o{}w{}d{} {} {} {} {}",
+            board_size, outer_size, win_length, depth, level_text(&self.levels[depth - 1]), results, to_move, forced,
+        )
+    }
+    /// Reads position written by `position`, game continues from it with given rules
+    pub fn from_position(text: &str, rules: Rules) -> Option<Game> {
+        let fields: Vec<&str> = text.split_whitespace().collect();
+        let [sizes, cells, results, to_move, forced] = fields[..] else {
+            return None;
+        };
+        let config = parse_sizes(sizes)?;
+        let mut game = Game::new(config, rules);
+        let mut level_texts: Vec<&str> = match config.depth {
+            1 if results == "-" => Vec::new(),
+            1 => return None,
+            _ => results.split('|').collect(),
+        };
+        level_texts.push(cells);
+        if level_texts.len() != config.depth {
+            return None;
+        }
+        for (level, level_text) in level_texts.into_iter().enumerate() {
+            let boards: Vec<&str> = level_text.split('/').collect();
+            if boards.len() != game.levels[level].len() {
+                return None;
+            }
+            for (board, board_text) in game.levels[level].iter_mut().zip(boards) {
+                let board_cells = board_text.chars().map(char_state).collect::<Option<Vec<BoardState>>>()?;
+                let is_leaf = level + 1 == config.depth;
+                if board_cells.len() != board.cells.len() || (is_leaf && board_cells.contains(&BoardState::Drawn)) {
+                    return None;
+                }
+                board.cells = board_cells;
+            }
+        }
+        let main_board = &game.levels[0][0];
+        game.state = match main_board.line_owner(config.win_length) {
+            Some(owner) => BoardState::Won(owner),
+            None if main_board.is_full() => BoardState::Drawn,
+            None => BoardState::Open,
+        };
+        // game ends with the first line on main board, so there can't be lines of both players
+        if !game.levels[0][0].allows_state(game.state, config.win_length, FinishedBoardRule::FreeMove) {
+            return None;
+        }
+        // results of upper levels must be results their boards can have
+        for level in 1..config.depth {
+            for (index, board) in game.levels[level].iter().enumerate() {
+                let state = game.levels[level - 1][index / config.outer_cells()].get(index % config.outer_cells());
+                if !board.allows_state(state, config.win_length, rules.finished_board) {
+                    return None;
+                }
+            }
+        }
+        for level in (0..config.depth).rev() {
+            for index in 0..game.levels[level].len() {
+                game.update_has_moves(level, index);
+            }
+        }
+        // lines are only known for boards that are won, the parent cell tells which ones
+        for level in 0..config.depth {
+            for index in 0..game.levels[level].len() {
+                let state = match level {
+                    0 => game.state,
+                    _ => game.levels[level - 1][index / config.outer_cells()].get(index % config.outer_cells()),
+                };
+                if let BoardState::Won(owner) = state {
+                    let board = &mut game.levels[level][index];
+                    board.line = board.find_line(config.win_length, owner);
+                }
+            }
+        }
+        game.to_move = match to_move {
+            "x" => Player::X,
+            "o" => Player::O,
+            _ => return None,
+        };
+        if forced != "-" {
+            for (level, letter) in forced.split('/').enumerate() {
+                let mut chars = letter.chars();
+                let cell = letter_index(chars.next()?)?;
+                if chars.next().is_some() || level + 1 >= config.depth || cell >= config.level_cells(level) {
+                    return None;
+                }
+                game.forced_board.push(cell);
+            }
+            if !game.is_playable(&game.forced_board) {
+                return None;
+            }
+        }
+        game.start = Some(Box::new(game.clone()));
+        Some(game)
+    }
+    /// Position string of the position game started from, None if it started from the empty board
+    pub fn start_position(&self) -> Option<String> {
+        self.start.as_ref().map(|start| start.position())
+    }
+    /// Amount of local boards in main board won by player
+    pub fn won_boards(&self, player: Player) -> usize {
+        let main_board = self.board(&[]);
+        (0..self.config.level_cells(0))
+            .filter(|cell| main_board.get(*cell) == BoardState::Won(player))
+            .count()
+    }
+    /// Result of the game, None while game is still going
+    pub fn outcome(&self) -> Option<Outcome> {
+        match self.state {
+            BoardState::Open => None,
+            BoardState::Won(owner) if self.rules.misere => Some(Outcome::MisereLine(owner)),
+            BoardState::Won(owner) => Some(Outcome::Line(owner)),
+            BoardState::Drawn if self.rules.majority_tiebreak => {
+                let (x, o) = (self.won_boards(Player::X), self.won_boards(Player::O));
+                Some(match x.cmp(&o) {
+                    std::cmp::Ordering::Greater => Outcome::Boards(Player::X),
+                    std::cmp::Ordering::Less => Outcome::Boards(Player::O),
+                    std::cmp::Ordering::Equal => Outcome::Draw,
+                })
+            },
+            BoardState::Drawn => Some(Outcome::Draw),
+        }
+    }
+}
+
+/// Makes up to `moves` moves or until the game ends, picking legal move number `moves made * stride` (wrapping)
+///
+/// Stride 0 always plays the first legal move. Shared by tests of every module that needs a game in progress
+#[cfg(test)]
+pub(crate) fn play_legal(game: &mut Game, stride: usize, moves: usize) {
+    for _ in 0..moves {
+        let legal = game.legal_moves();
+        if legal.is_empty() {
+            break;
+        }
+        game.apply(legal[game.history().len() * stride % legal.len()]).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Plain tic-tac-toe, moves are single cell indices
+    fn tic_tac_toe() -> Game {
+        let config = GridConfig { depth: 1, ..GridConfig::default() };
+        Game::new(config, Rules::default())
+    }
+
+    fn play(game: &mut Game, paths: &[&[usize]]) {
+        for path in paths {
+            game.apply(Move::new(path)).unwrap();
+        }
+    }
+
+    /// Default sized game under the rule played until it ends, see `play_legal`
+    fn play_out(rule: FinishedBoardRule, stride: usize) -> Game {
+        let mut game = Game::new(GridConfig::default(), Rules { finished_board: rule, ..Rules::default() });
+        play_legal(&mut game, stride, usize::MAX);
+        game
+    }
+
+    #[test]
+    fn depth_is_limited_by_cell_count() {
+        let small = GridConfig::default();
+        assert_eq!(small.max_depth(), 4);
+        let big = GridConfig { board_size: 6, outer_size: 6, ..GridConfig::default() };
+        assert_eq!(big.max_depth(), 2);
+        assert!(!GridConfig { depth: 3, ..big }.is_valid());
+    }
+
+    #[test]
+    fn empty_board_allows_every_cell() {
+        let game = Game::default();
+        let moves = game.legal_moves();
+        assert_eq!(moves.len(), 81);
+        assert!(moves.iter().all(|mv| game.is_legal(*mv)));
+    }
+
+    #[test]
+    fn forced_board_limits_moves() {
+        let mut game = Game::default();
+        play(&mut game, &[&[0, 4]]);
+        assert_eq!(game.forced_board(), &[4]);
+        let moves = game.legal_moves();
+        assert_eq!(moves.len(), 9);
+        assert!(moves.iter().all(|mv| mv.path()[0] == 4));
+    }
+
+    #[test]
+    fn illegal_moves_leave_game_untouched() {
+        let mut game = Game::default();
+        play(&mut game, &[&[0, 4], &[4, 4]]);
+        let before = game.clone();
+        assert_eq!(game.apply(Move::new(&[0, 0])), Err(MoveError::WrongBoard));
+        assert_eq!(game.apply(Move::new(&[4, 4])), Err(MoveError::Occupied));
+        assert_eq!(game.apply(Move::new(&[4, 9])), Err(MoveError::OutOfRange));
+        assert_eq!(game.apply(Move::new(&[4])), Err(MoveError::OutOfRange));
+        assert_eq!(game, before);
+    }
+
+    #[test]
+    fn moves_out_of_turn_are_reported_first() {
+        let mut game = Game::default();
+        play(&mut game, &[&[0, 4]]);
+        // wrong board and occupied cell, but it isn't O's turn anyway
+        assert_eq!(game.validate_as(Player::O, Move::new(&[0, 4])), Err(MoveError::NotYourTurn));
+        assert_eq!(game.validate_as(Player::X, Move::new(&[0, 4])), Err(MoveError::WrongBoard));
+        assert_eq!(game.apply_as(Player::O, Move::new(&[4, 0])), Err(MoveError::NotYourTurn));
+        assert_eq!(game.apply_as(Player::X, Move::new(&[4, 0])), Ok(()));
+    }
+
+    #[test]
+    fn line_wins_and_ends_game() {
+        let mut game = tic_tac_toe();
+        play(&mut game, &[&[0], &[3], &[1], &[4], &[2]]);
+        assert_eq!(game.outcome(), Some(Outcome::Line(Player::O)));
+        assert_eq!(game.line(&[]), Some(Line { start: 0, end: 2, owner: Player::O }));
+        assert_eq!(game.apply(Move::new(&[5])), Err(MoveError::GameOver));
+        assert!(game.legal_moves().is_empty());
+    }
+
+    #[test]
+    fn misere_line_loses() {
+        let mut game = Game::new(tic_tac_toe().config(), Rules { misere: true, ..Rules::default() });
+        play(&mut game, &[&[0], &[3], &[1], &[4], &[2]]);
+        assert_eq!(game.outcome(), Some(Outcome::MisereLine(Player::O)));
+        assert_eq!(game.outcome().and_then(|outcome| outcome.winner()), Some(Player::X));
+        // misère doesn't turn the board count around
+        let drawn = play_out(FinishedBoardRule::FreeMove, 8);
+        assert!(drawn.won_boards(Player::X) > drawn.won_boards(Player::O));
+        let rules = Rules { majority_tiebreak: true, misere: true, ..Rules::default() };
+        let game = Game::from_history(drawn.config(), rules, drawn.history()).unwrap();
+        assert_eq!(game.outcome(), Some(Outcome::Boards(Player::X)));
+    }
+
+    #[test]
+    fn full_board_without_line_is_draw() {
+        let mut game = tic_tac_toe();
+        play(&mut game, &[&[4], &[0], &[2], &[6], &[3], &[5], &[1], &[7], &[8]]);
+        assert_eq!(game.outcome(), Some(Outcome::Draw));
+        assert_eq!(game.line(&[]), None);
+    }
+
+    #[test]
+    fn games_end_with_win_under_every_rule() {
+        for (rule, winner) in [
+            (FinishedBoardRule::FreeMove, Player::X),
+            (FinishedBoardRule::PlayInWonBoards, Player::O),
+            (FinishedBoardRule::WonBoardsDrawOnly, Player::O),
+        ] {
+            let game = play_out(rule, 0);
+            assert_eq!(game.outcome(), Some(Outcome::Line(winner)), "{:?}", rule);
+            assert_eq!(game.line(&[]).map(|line| line.owner), Some(winner), "{:?}", rule);
+        }
+    }
+
+    #[test]
+    fn games_end_with_draw_under_every_rule() {
+        for (rule, stride) in [
+            (FinishedBoardRule::FreeMove, 2),
+            (FinishedBoardRule::PlayInWonBoards, 1),
+            (FinishedBoardRule::WonBoardsDrawOnly, 1),
+        ] {
+            let game = play_out(rule, stride);
+            assert_eq!(game.outcome(), Some(Outcome::Draw), "{:?}", rule);
+            assert_eq!(game.board(&[]).line_owner(game.config().win_length), None, "{:?}", rule);
+        }
+    }
+
+    #[test]
+    fn majority_tiebreak_decides_drawn_games() {
+        let tiebreak = Rules { majority_tiebreak: true, ..Rules::default() };
+        for (rule, stride, outcome) in [
+            (FinishedBoardRule::FreeMove, 8, Outcome::Boards(Player::X)),
+            (FinishedBoardRule::FreeMove, 2, Outcome::Boards(Player::O)),
+            // four boards each
+            (FinishedBoardRule::PlayInWonBoards, 5, Outcome::Draw),
+        ] {
+            let drawn = play_out(rule, stride);
+            // tiebreak is off by default
+            assert_eq!(drawn.outcome(), Some(Outcome::Draw), "{:?} {}", rule, stride);
+            let rules = Rules { finished_board: rule, ..tiebreak };
+            let game = Game::from_history(drawn.config(), rules, drawn.history()).unwrap();
+            assert_eq!(game.outcome(), Some(outcome), "{:?} {}", rule, stride);
+        }
+    }
+
+    #[test]
+    fn positions_round_trip() {
+        for rule in FinishedBoardRule::ALL {
+            let rules = Rules { finished_board: rule, ..Rules::default() };
+            for stride in [0, 1, 2] {
+                let game = play_out(rule, stride);
+                for moves in 0..=game.history().len() {
+                    let expected = game.replayed(moves);
+                    let position = expected.position();
+                    let read = Game::from_position(&position, rules).unwrap();
+                    assert_eq!(read.position(), position);
+                    assert_eq!(read.legal_moves(), expected.legal_moves());
+                    assert_eq!(read.outcome(), expected.outcome());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn boards_without_moves_are_tracked_while_playing() {
+        let has_moves = |game: &Game| -> Vec<bool> { game.levels.iter().flatten().map(|board| board.has_moves).collect() };
+        let config = GridConfig { depth: 3, ..GridConfig::default() };
+        for rule in FinishedBoardRule::ALL {
+            let rules = Rules { finished_board: rule, ..Rules::default() };
+            let mut game = Game::new(config, rules);
+            while game.outcome().is_none() {
+                play_legal(&mut game, 3, 40);
+                // read position works everything out from its cells
+                let read = Game::from_position(&game.position(), rules).unwrap();
+                assert_eq!(read.legal_moves(), game.legal_moves(), "{:?}", rule);
+                assert_eq!(has_moves(&read), has_moves(&game), "{:?}", rule);
+            }
+        }
+    }
+
+    #[test]
+    fn won_boards_with_drawn_cells_round_trip() {
+        let config = GridConfig { depth: 3, ..GridConfig::default() };
+        let rules = Rules { finished_board: FinishedBoardRule::WonBoardsDrawOnly, ..Rules::default() };
+        let mut game = Game::new(config, rules);
+        play_legal(&mut game, 2, 359);
+        // a board of the winning line in middle board turned into a draw, middle board stays won
+        let broken = (0..config.outer_cells()).any(|cell| match game.board_state(&[cell]) {
+            BoardState::Won(owner) => !game.board(&[cell]).has_line(config.win_length, owner),
+            _ => false,
+        });
+        assert!(broken);
+        let position = game.position();
+        let read = Game::from_position(&position, rules).unwrap();
+        assert_eq!(read.position(), position);
+        assert_eq!(read.legal_moves(), game.legal_moves());
+    }
+
+    #[test]
+    fn positions_with_impossible_results_are_rejected() {
+        let empty = ["........."; 9].join("/");
+        let won = format!("xxx....../{}", ["........."; 8].join("/"));
+        let rules = Rules::default();
+        assert!(Game::from_position(&format!("b3o3w3d2 {} ......... o -", empty), rules).is_some());
+        // board 0 claimed by X without any marks
+        assert!(Game::from_position(&format!("b3o3w3d2 {} x........ o -", empty), rules).is_none());
+        // X line in board 0 that main board doesn't know about
+        assert!(Game::from_position(&format!("b3o3w3d2 {} ......... o -", won), rules).is_none());
+        assert!(Game::from_position(&format!("b3o3w3d2 {} o........ o -", won), rules).is_none());
+        let game = Game::from_position(&format!("b3o3w3d2 {} x........ o -", won), rules).unwrap();
+        assert_eq!(game.board_state(&[0]), BoardState::Won(Player::X));
+        assert_eq!(game.line(&[0]), Some(Line { start: 0, end: 2, owner: Player::X }));
+    }
+
+    #[test]
+    fn undo_and_redo_follow_history() {
+        let mut game = Game::default();
+        play(&mut game, &[&[0, 4], &[4, 0], &[0, 8], &[8, 0]]);
+        let full = game.clone();
+        assert_eq!(game.undo(), Some(Move::new(&[8, 0])));
+        assert_eq!(game.undo(), Some(Move::new(&[0, 8])));
+        assert_eq!(game.history(), full.replayed(2).history());
+        assert_eq!(game.position(), full.replayed(2).position());
+        assert!(game.can_redo());
+        game.redo();
+        game.redo();
+        assert_eq!(game.position(), full.position());
+        assert!(!game.can_redo());
+        // a new move after undo drops undone moves
+        game.undo();
+        play(&mut game, &[&[8, 1]]);
+        assert!(!game.can_redo());
+        assert_eq!(game.replayed(0), Game::default());
+    }
+}
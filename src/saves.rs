@@ -0,0 +1,64 @@
+//! Games saved as records in the saves directory next to the executable's working directory
+
+use std::{fs, path::PathBuf, time::SystemTime};
+
+use crate::notation::GameRecord;
+
+/// Directory saved games are kept in
+const SAVE_DIR: &str = "saves";
+/// Extension of saved game files
+const SAVE_EXTENSION: &str = "ktt";
+
+fn save_path(name: &str) -> PathBuf {
+    PathBuf::from(SAVE_DIR).join(format!("{}.{}", name, SAVE_EXTENSION))
+}
+
+/// Writes record to the saves directory, returns the path of the file
+pub fn save_game(name: &str, record: &GameRecord) -> Result<PathBuf, String> {
+    if name.is_empty() || name.contains(['/', '\\', '.']) {
+        return Err(format!("\"{}\" can't be used as save name", name));
+    }
+    let path = save_path(name);
+    fs::create_dir_all(SAVE_DIR).map_err(|error| error.to_string())?;
+    fs::write(&path, record.to_string()).map_err(|error| error.to_string())?;
+    Ok(path)
+}
+
+/// Reads saved record by the name it was saved with
+pub fn load_game(name: &str) -> Result<GameRecord, String> {
+    let text = fs::read_to_string(save_path(name)).map_err(|error| error.to_string())?;
+    text.parse().map_err(|error| format!("{}", error))
+}
+
+/// Names of every saved game, sorted
+pub fn saved_games() -> Vec<String> {
+    let Ok(entries) = fs::read_dir(SAVE_DIR) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == SAVE_EXTENSION))
+        .filter_map(|path| path.file_stem().map(|name| name.to_string_lossy().to_string()))
+        .collect();
+    names.sort();
+    names
+}
+
+/// Today's date in UTC written as `YYYY.MM.DD`
+pub fn today() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    // days since 1970-01-01 to civil date, shifted to years starting in March
+    let days = (seconds / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}.{:02}.{:02}", year, month, day)
+}
@@ -0,0 +1,123 @@
+//! Headless bot against bot games for benchmarking engines:
+//!
+//! ```text
+//! kit-tak simulate --games 100 --a mcts:2000 --b alphabeta:hard --variant "board=3 outer=3 win=3 depth=2" --seed 7
+//! ```
+//!
+//! Bots are `mcts:<iterations>`, `mcts:<milliseconds>ms` or `alphabeta:easy|medium|hard`.
+//! Bots take turns starting the game, so both get the first move equally often.
+
+use std::{sync::atomic::AtomicBool, time::Duration};
+
+use crate::{bot::{alpha_beta::Difficulty, BotKind, BotSettings, Budget, Rng}, notation::parse_variant, rules::{Game, GridConfig, Player, Rules, FIRST_PLAYER}};
+
+/// Games played when `--games` is not given
+const DEFAULT_GAMES: usize = 20;
+
+/// Reads bot written like `mcts:2000`, `mcts:500ms` or `alphabeta:hard`
+fn parse_bot(text: &str) -> Result<BotSettings, String> {
+    let mut settings = BotSettings::default();
+    let (kind, option) = text.split_once(':').unwrap_or((text, ""));
+    match kind {
+        "mcts" => {
+            settings.kind = BotKind::Mcts;
+            if let Some(millis) = option.strip_suffix("ms") {
+                let millis = millis.parse().map_err(|_| format!("bad time in bot \"{}\"", text))?;
+                settings.budget = Budget::Time(Duration::from_millis(millis));
+            } else if !option.is_empty() {
+                settings.budget = Budget::Iterations(option.parse().map_err(|_| format!("bad iterations in bot \"{}\"", text))?);
+            }
+        },
+        "alphabeta" => {
+            settings.kind = BotKind::AlphaBeta;
+            settings.difficulty = Difficulty::ALL
+                .into_iter()
+                .find(|difficulty| difficulty.name().eq_ignore_ascii_case(option) || option.is_empty() && *difficulty == Difficulty::Medium)
+                .ok_or_else(|| format!("unknown difficulty in bot \"{}\"", text))?;
+        },
+        _ => return Err(format!("unknown bot \"{}\"", text)),
+    }
+    Ok(settings)
+}
+
+/// Plays one game, returns the winner and amount of moves made
+fn play(config: GridConfig, rules: Rules, bots: [&BotSettings; 2], first: usize, rng: &mut Rng) -> (Option<usize>, usize) {
+    let stop = AtomicBool::new(false);
+    let mut game = Game::new(config, rules);
+    // bot index playing each player
+    let bot_of = |player: Player| if player == FIRST_PLAYER { first } else { 1 - first };
+    while game.outcome().is_none() {
+        let bot = bots[bot_of(game.to_move())];
+        let Some(mv) = bot.best_move(&game, rng, &stop, bot.difficulty.time_limit()) else {
+            break;
+        };
+        game.apply(mv).expect("bots make legal moves");
+    }
+    let winner = game.outcome().and_then(|outcome| outcome.winner()).map(bot_of);
+    (winner, game.history().len())
+}
+
+/// Runs simulation with arguments after `simulate`, prints statistics to stdout
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut games = DEFAULT_GAMES;
+    let mut bots = [BotSettings::default(), BotSettings::default()];
+    let mut names = ["mcts".to_string(), "mcts".to_string()];
+    let (mut config, mut rules) = (GridConfig::default(), Rules::default());
+    let mut seed = None;
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or_else(|| format!("{} needs a value", flag))?;
+        match flag.as_str() {
+            "--games" => games = value.parse().map_err(|_| format!("bad amount of games \"{}\"", value))?,
+            "--a" => (bots[0], names[0]) = (parse_bot(value)?, value.clone()),
+            "--b" => (bots[1], names[1]) = (parse_bot(value)?, value.clone()),
+            "--variant" => (config, rules) = parse_variant(value).map_err(|error| error.to_string())?,
+            "--seed" => seed = Some(value.parse().map_err(|_| format!("bad seed \"{}\"", value))?),
+            _ => return Err(format!("unknown option {}", flag)),
+        }
+    }
+    let mut rng = match seed {
+        Some(seed) => Rng::new(seed),
+        None => Rng::from_time(),
+    };
+    let mut wins = [0, 0];
+    let mut draws = 0;
+    let mut first_player_wins = 0;
+    let mut second_player_wins = 0;
+    let mut total_moves = 0;
+    for index in 0..games {
+        let first = index % 2;
+        let (winner, moves) = play(config, rules, [&bots[0], &bots[1]], first, &mut rng);
+        total_moves += moves;
+        match winner {
+            Some(bot) => {
+                wins[bot] += 1;
+                if bot == first {
+                    first_player_wins += 1;
+                } else {
+                    second_player_wins += 1;
+                }
+            },
+            None => draws += 1,
+        }
+        println!("game {}: {} started, {} moves, {}", index + 1, names[first], moves, match winner {
+            Some(bot) => format!("{} won", names[bot]),
+            None => "draw".to_string(),
+        });
+    }
+    let percent = |count: usize| count as f32 * 100. / games.max(1) as f32;
+    println!();
+    println!("{} games", games);
+    println!("A {}: {} wins ({:.1}%)", names[0], wins[0], percent(wins[0]));
+    println!("B {}: {} wins ({:.1}%)", names[1], wins[1], percent(wins[1]));
+    println!("draws: {} ({:.1}%)", draws, percent(draws));
+    println!("average game length: {:.1} moves", total_moves as f32 / games.max(1) as f32);
+    println!(
+        "first player: {} wins ({:.1}%), second player: {} wins ({:.1}%)",
+        first_player_wins,
+        percent(first_player_wins),
+        second_player_wins,
+        percent(second_player_wins),
+    );
+    Ok(())
+}
@@ -0,0 +1,51 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use bevy_egui::{egui::{self, Align2, Color32}, EguiContexts};
+
+pub struct ToastPlugin;
+
+impl Plugin for ToastPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Toasts>()
+        .add_systems(Update, toast_ui_system);
+    }
+}
+
+/// Seconds a toast stays on screen
+const TOAST_SECONDS: f32 = 3.;
+
+/// Short messages shown to the player for a few seconds
+#[derive(Resource, Default)]
+pub struct Toasts(VecDeque<(String, f32)>);
+
+impl Toasts {
+    /// Shows message to the player
+    pub fn push(&mut self, message: impl Into<String>) {
+        let message = message.into();
+        info!("toast: {}", message);
+        self.0.push_back((message, TOAST_SECONDS));
+    }
+}
+
+/// Draws toasts at the bottom of the window and removes expired ones
+fn toast_ui_system(
+    mut contexts: EguiContexts,
+    mut toasts: ResMut<Toasts>,
+    time: Res<Time>,
+) {
+    if toasts.0.is_empty() {
+        return;
+    }
+    for (_, seconds_left) in &mut toasts.0 {
+        *seconds_left -= time.delta_seconds();
+    }
+    toasts.0.retain(|(_, seconds_left)| *seconds_left > 0.);
+    egui::Area::new(egui::Id::new("toasts")).anchor(Align2::CENTER_BOTTOM, [0., -20.]).show(contexts.ctx_mut(), |ui| {
+        for (message, _) in &toasts.0 {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                ui.colored_label(Color32::LIGHT_RED, message);
+            });
+        }
+    });
+}